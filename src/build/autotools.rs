@@ -0,0 +1,223 @@
+use crate::BuildStep;
+use crate::util::execute_build_command;
+use std::process::Command;
+use crate::build::{BuildResult, Build, BuildStepError, LibraryType};
+use crate::build::toolchain::Toolchain;
+use crate::build::jobs::configure_make;
+use crate::build::library::{register_installed_libraries, list_files, InstalledLibrary};
+use std::hash::{Hasher, Hash};
+use std::path::PathBuf;
+
+pub struct AutotoolsBuild {
+    autoreconf: bool,
+    configure_arguments: Vec<String>
+}
+
+impl AutotoolsBuild {
+    pub fn builder() -> AutotoolsBuildBuilder {
+        AutotoolsBuildBuilder::new()
+    }
+
+    fn configure_arguments(&self, build: &Build) -> Vec<String> {
+        let mut arguments = Vec::with_capacity(self.configure_arguments.len() + 4);
+        arguments.push(format!("--prefix={}", build.install_prefix().to_str().expect("invalid install prefix")));
+
+        match build.library_type() {
+            LibraryType::Shared => {
+                arguments.push("--enable-shared".to_owned());
+                arguments.push("--disable-static".to_owned());
+            },
+            LibraryType::Static => {
+                arguments.push("--enable-static".to_owned());
+                arguments.push("--disable-shared".to_owned());
+                /* the static library will be linked into rust binaries which are position independent */
+                arguments.push("--with-pic".to_owned());
+            },
+        };
+
+        arguments.extend(self.configure_arguments.iter().cloned());
+        arguments
+    }
+}
+
+impl BuildStep for AutotoolsBuild {
+    fn name(&self) -> &str {
+        "autotools build"
+    }
+
+    fn hash(&self, hasher: &mut Box<dyn Hasher>) {
+        self.autoreconf.hash(hasher);
+        self.configure_arguments.hash(hasher);
+        Toolchain::from_env().hash(hasher);
+    }
+
+    fn execute(&mut self, build: &Build, result: &mut BuildResult) -> Result<(), BuildStepError> {
+        let source_path = build.source().local_directory();
+        let toolchain = Toolchain::from_env().with_compiler_launcher(build.compiler_launcher().map(PathBuf::as_path));
+        toolchain.emit_rerun_if_env_changed(result);
+
+        if self.autoreconf {
+            let mut command = Command::new("autoreconf");
+            toolchain.apply(&mut command);
+            command.arg("--force")
+                .arg("--install")
+                .current_dir(source_path);
+            execute_build_command(&mut command, "failed to generate configure script")?;
+        }
+
+        let configure_script = source_path.join("configure");
+        if !configure_script.is_file() {
+            return Err(BuildStepError::new_simple(format!("missing configure script at {:?} (consider enabling autoreconf)", configure_script)));
+        }
+
+        /* configure (out of tree) */
+        {
+            let mut command = Command::new(&configure_script);
+            toolchain.apply(&mut command);
+            command.args(self.configure_arguments(build))
+                .current_dir(build.build_path());
+            execute_build_command(&mut command, "failed to configure build")?;
+        }
+
+        /* compile */
+        {
+            let mut command = Command::new("make");
+            toolchain.apply(&mut command);
+            command.current_dir(build.build_path());
+            configure_make(&mut command);
+            execute_build_command(&mut command, "failed to build")?;
+        }
+
+        /* install */
+        {
+            /* Stage the install first to learn which files it installs, including files which are reinstalled unchanged.
+             * The install prefix itself may be shared with other builds and doesn't need to be scanned. */
+            let staging_path = build.build_path().join("install_staging");
+            let _ = std::fs::remove_dir_all(&staging_path);
+
+            let mut command = Command::new("make");
+            toolchain.apply(&mut command);
+            command.arg("install")
+                .arg(format!("DESTDIR={}", staging_path.to_str().expect("invalid build path")))
+                .current_dir(build.build_path());
+            execute_build_command(&mut command, "failed to stage install")?;
+
+            let installed_files = list_files(&staging_path)
+                .map_err(|err| BuildStepError::new_io("failed to scan staged install", err))?;
+            let _ = std::fs::remove_dir_all(&staging_path);
+
+            let mut command = Command::new("make");
+            toolchain.apply(&mut command);
+            command.arg("install")
+                .current_dir(build.build_path());
+            execute_build_command(&mut command, "failed to install build")?;
+
+            /* DESTDIR has been prepended to the absolute install paths */
+            let root = build.install_prefix().ancestors().last().expect("invalid install prefix");
+            let installed_libraries = installed_files.into_iter()
+                .map(|file| root.join(file))
+                .filter_map(InstalledLibrary::from_file);
+
+            register_installed_libraries(installed_libraries, build.library_type(), result);
+        }
+
+        Ok(())
+    }
+}
+
+pub struct AutotoolsBuildBuilder {
+    inner: AutotoolsBuild
+}
+
+impl AutotoolsBuildBuilder {
+    fn new() -> Self {
+        AutotoolsBuildBuilder{
+            inner: AutotoolsBuild{
+                autoreconf: false,
+                configure_arguments: Vec::new()
+            }
+        }
+    }
+
+    /// Run `autoreconf --force --install` within the source directory before configuring.
+    /// Required for sources which don't ship a pre generated configure script.
+    pub fn autoreconf(mut self, enabled: bool) -> Self {
+        self.inner.autoreconf = enabled;
+        self
+    }
+
+    /// Append an additional argument for the configure script
+    pub fn configure_argument<V>(mut self, argument: V) -> Self
+        where V: Into<String>
+    {
+        self.inner.configure_arguments.push(argument.into());
+        self
+    }
+
+    pub fn build(self) -> AutotoolsBuild {
+        self.inner
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::build::{AutotoolsBuild, LibraryType};
+    use crate::source::test::DummyBuildSource;
+    use crate::Build;
+
+    /* A minimal configure script which behaves like an autotools generated one */
+    const CONFIGURE_SCRIPT: &str = r#"#!/bin/sh
+for arg in "$@"; do
+    case "$arg" in
+        --prefix=*) prefix="${arg#--prefix=}" ;;
+    esac
+done
+# installs like `install -C`, which keeps unchanged files untouched
+printf 'all:\n\ttouch libanswer.a\ninstall:\n\tmkdir -p $(DESTDIR)%s/lib\n\tcmp -s libanswer.a $(DESTDIR)%s/lib/libanswer.a || cp libanswer.a $(DESTDIR)%s/lib/\n' "$prefix" "$prefix" "$prefix" > Makefile
+"#;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_build_configure_project() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let base_url = std::env::temp_dir().join("__test_autotools");
+        let _ = std::fs::remove_dir_all(&base_url);
+
+        let source_path = base_url.join("source");
+        std::fs::create_dir_all(&source_path).unwrap();
+        std::fs::write(source_path.join("configure"), CONFIGURE_SCRIPT).unwrap();
+        std::fs::set_permissions(source_path.join("configure"), std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let execute_build = || {
+            let autotools = AutotoolsBuild::builder()
+                .configure_argument("--disable-tests")
+                .build();
+
+            let mut build = Build::builder()
+                .name("autotools-test")
+                .source(Box::new(DummyBuildSource{ path: source_path.clone() }))
+                .add_step(Box::new(autotools))
+                .build_path(base_url.clone())
+                .install_prefix(base_url.join("install_root"))
+                .library_type(LibraryType::Static)
+                .build()
+                .expect("failed to create build");
+
+            match build.execute() {
+                Err(error) => panic!("{}", error.pretty_format()),
+                Ok(result) => result
+            }
+        };
+
+        /* the second install keeps the unchanged library untouched, but it must be registered anyways */
+        for _ in 0..2 {
+            let result = execute_build();
+            assert_eq!(result.libraries().iter().map(|library| library.to_string()).collect::<Vec<_>>(), vec!["static=answer"]);
+            assert_eq!(result.library_paths().iter().map(|path| path.to_string()).collect::<Vec<_>>(),
+                       vec![format!("native={}", base_url.join("install_root").join("lib").to_string_lossy())]);
+        }
+
+        std::fs::remove_dir_all(&base_url).unwrap();
+    }
+}
@@ -0,0 +1,87 @@
+use std::path::{PathBuf, Path};
+use std::process::Command;
+
+/// A compiler cache which will be used to launch the C/C++ compilers of the build steps.
+/// The compiler cache isn't part of the build hash, enabling it will not cause a rebuild.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompilerCache {
+    /// Use sccache or ccache, whichever is available
+    Auto,
+    Ccache,
+    Sccache,
+    /// A custom compiler launcher, either a path or a program name within the `PATH`
+    Custom(PathBuf)
+}
+
+impl CompilerCache {
+    /// Parse the value of the `compiler_cache` environment variable.
+    /// Returns `None` if the compiler cache has been disabled (`none`, `off`, `false` or `0`).
+    pub(crate) fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "" | "none" | "off" | "false" | "0" => None,
+            "auto" | "on" | "true" | "1" => Some(CompilerCache::Auto),
+            "ccache" => Some(CompilerCache::Ccache),
+            "sccache" => Some(CompilerCache::Sccache),
+            _ => Some(CompilerCache::Custom(PathBuf::from(value.trim())))
+        }
+    }
+
+    /// Find the executable of the compiler cache
+    pub fn launcher(&self) -> Option<PathBuf> {
+        match self {
+            CompilerCache::Auto => find_program("sccache").or_else(|| find_program("ccache")),
+            CompilerCache::Ccache => find_program("ccache"),
+            CompilerCache::Sccache => find_program("sccache"),
+            CompilerCache::Custom(path) if path.components().count() > 1 => Some(path.clone()).filter(|path| path.is_file()),
+            CompilerCache::Custom(program) => program.to_str().and_then(find_program)
+        }
+    }
+}
+
+/// Find an executable within the directories of the `PATH`
+fn find_program(name: &str) -> Option<PathBuf> {
+    let paths = std::env::var_os("PATH")?;
+    std::env::split_paths(&paths)
+        .flat_map(|directory| {
+            let program = directory.join(name);
+            let executable = Some(program.with_extension(std::env::consts::EXE_EXTENSION))
+                .filter(|_| !std::env::consts::EXE_EXTENSION.is_empty());
+            std::iter::once(program).chain(executable)
+        })
+        .find(|program| program.is_file())
+}
+
+/// Print the statistics of the compiler cache.
+/// ccache and sccache both support `--show-stats`.
+pub(crate) fn print_statistics(launcher: &Path) {
+    let mut command = Command::new(launcher);
+    command.arg("--show-stats");
+    match command.output() {
+        Ok(output) if output.status.success() => {
+            println!("Compiler cache statistics ({}):", launcher.to_string_lossy());
+            println!("{}", String::from_utf8_lossy(&output.stdout).trim_end());
+        },
+        Ok(output) => eprintln!("Failed to query compiler cache statistics: {}", String::from_utf8_lossy(&output.stderr).trim_end()),
+        Err(error) => eprintln!("Failed to query compiler cache statistics: {:?}", error)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::build::CompilerCache;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_compiler_cache() {
+        assert_eq!(CompilerCache::parse("off"), None);
+        assert_eq!(CompilerCache::parse("0"), None);
+        assert_eq!(CompilerCache::parse("auto"), Some(CompilerCache::Auto));
+        assert_eq!(CompilerCache::parse("SCCache"), Some(CompilerCache::Sccache));
+        assert_eq!(CompilerCache::parse("/opt/bin/buildcache"), Some(CompilerCache::Custom(PathBuf::from("/opt/bin/buildcache"))));
+
+        assert_eq!(CompilerCache::Custom(PathBuf::from("/__missing/ccache")).launcher(), None);
+        assert_eq!(CompilerCache::Custom(PathBuf::from("__missing_compiler_cache")).launcher(), None);
+        #[cfg(unix)]
+        assert_eq!(CompilerCache::Custom(PathBuf::from("sh")).launcher().and_then(|path| path.file_name().map(|name| name.to_owned())), Some("sh".into()));
+    }
+}
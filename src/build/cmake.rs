@@ -0,0 +1,223 @@
+use crate::BuildStep;
+use crate::util::execute_build_command;
+use std::process::Command;
+use crate::build::{BuildResult, Build, BuildStepError, LibraryType};
+use crate::build::toolchain::Toolchain;
+use crate::build::jobs::JobReservation;
+use crate::build::library::{register_installed_libraries, InstalledLibrary};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::hash::{Hasher, Hash};
+
+pub struct CMakeBuild {
+    cmake_options: BTreeMap<String, String>,
+    generator: Option<String>,
+    build_type: Option<String>
+}
+
+impl CMakeBuild {
+    pub fn builder() -> CMakeBuildBuilder {
+        CMakeBuildBuilder::new()
+    }
+
+    fn configure_arguments(&self, build: &Build) -> Vec<String> {
+        let mut arguments = Vec::with_capacity(self.cmake_options.len() + 8);
+
+        let source_path = build.source().local_directory().to_str().expect("invalid source path");
+        let build_path = build.build_path().to_str().expect("invalid build path");
+        arguments.push("-S".to_owned());
+        arguments.push(source_path.to_owned());
+        arguments.push("-B".to_owned());
+        arguments.push(build_path.to_owned());
+
+        if let Some(generator) = &self.generator {
+            arguments.push("-G".to_owned());
+            arguments.push(generator.clone());
+        }
+
+        arguments.push(format!("-DCMAKE_INSTALL_PREFIX={}", build.install_prefix().to_str().expect("invalid install prefix")));
+        match build.library_type() {
+            LibraryType::Shared => arguments.push("-DBUILD_SHARED_LIBS=ON".to_owned()),
+            LibraryType::Static => arguments.push("-DBUILD_SHARED_LIBS=OFF".to_owned()),
+        };
+
+        if let Some(build_type) = &self.build_type {
+            arguments.push(format!("-DCMAKE_BUILD_TYPE={}", build_type));
+        }
+
+        if let Some(launcher) = build.compiler_launcher() {
+            let launcher = launcher.to_str().expect("invalid compiler launcher");
+            arguments.push(format!("-DCMAKE_C_COMPILER_LAUNCHER={}", launcher));
+            arguments.push(format!("-DCMAKE_CXX_COMPILER_LAUNCHER={}", launcher));
+        }
+
+        self.cmake_options.iter().for_each(|(key, value)| {
+            arguments.push(format!("-D{}={}", key, value));
+        });
+
+        arguments
+    }
+}
+
+impl BuildStep for CMakeBuild {
+    fn name(&self) -> &str {
+        "cmake build"
+    }
+
+    fn hash(&self, hasher: &mut Box<dyn Hasher>) {
+        self.cmake_options.iter().for_each(|(key, value)| {
+            key.hash(hasher);
+            value.hash(hasher);
+        });
+        self.generator.hash(hasher);
+        self.build_type.hash(hasher);
+        Toolchain::from_env().hash(hasher);
+    }
+
+    fn execute(&mut self, build: &Build, result: &mut BuildResult) -> Result<(), BuildStepError> {
+        let build_path = build.build_path().to_str().expect("invalid build path");
+        let toolchain = Toolchain::from_env();
+        toolchain.emit_rerun_if_env_changed(result);
+
+        /* configure */
+        {
+            let mut command = Command::new("cmake");
+            toolchain.apply(&mut command);
+            command.args(self.configure_arguments(build));
+            execute_build_command(&mut command, "failed to configure build")?;
+        }
+
+        /* compile */
+        {
+            let mut command = Command::new("cmake");
+            toolchain.apply(&mut command);
+            command.arg("--build");
+            command.arg(build_path);
+            if let Some(build_type) = &self.build_type {
+                /* required for multi config generators like Visual Studio */
+                command.args(["--config", build_type]);
+            }
+
+            let reservation = JobReservation::acquire();
+            command.arg("--parallel").arg(reservation.jobs().to_string());
+            execute_build_command(&mut command, "failed to build")?;
+        }
+
+        /* install */
+        {
+            let mut command = Command::new("cmake");
+            toolchain.apply(&mut command);
+            command.arg("--install");
+            command.arg(build_path);
+            if let Some(build_type) = &self.build_type {
+                command.args(["--config", build_type]);
+            }
+            execute_build_command(&mut command, "failed to install build")?;
+
+            /* CMake records every installed file within the install manifest */
+            let manifest_path = build.build_path().join("install_manifest.txt");
+            let manifest = std::fs::read_to_string(&manifest_path)
+                .map_err(|err| BuildStepError::new_io("failed to read cmake install manifest", err))?;
+
+            let installed_libraries = manifest.lines()
+                .filter(|line| !line.is_empty())
+                .map(PathBuf::from)
+                .filter_map(InstalledLibrary::from_file);
+
+            register_installed_libraries(installed_libraries, build.library_type(), result);
+        }
+
+        Ok(())
+    }
+}
+
+pub struct CMakeBuildBuilder {
+    inner: CMakeBuild
+}
+
+impl CMakeBuildBuilder {
+    fn new() -> Self {
+        CMakeBuildBuilder{
+            inner: CMakeBuild{
+                cmake_options: BTreeMap::new(),
+                generator: None,
+                build_type: None
+            }
+        }
+    }
+
+    /// Set a cmake cache entry which will be passed as `-D<key>=<value>`
+    pub fn cmake_option<K, V>(mut self, key: K, value: V) -> Self
+        where K: Into<String>,
+              V: Into<String>
+    {
+        self.inner.cmake_options.insert(key.into(), value.into());
+        self
+    }
+
+    /// Set the cmake generator (e.g. "Ninja" or "Unix Makefiles")
+    pub fn generator(mut self, generator: Option<String>) -> Self {
+        self.inner.generator = generator;
+        self
+    }
+
+    /// Set the `CMAKE_BUILD_TYPE` (e.g. "Release" or "Debug")
+    pub fn build_type(mut self, build_type: Option<String>) -> Self {
+        self.inner.build_type = build_type;
+        self
+    }
+
+    pub fn build(self) -> CMakeBuild {
+        self.inner
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::build::{CMakeBuild, CompilerCache, LibraryType};
+    use crate::source::test::DummyBuildSource;
+    use crate::Build;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_configure_arguments() {
+        let base_url = std::env::temp_dir().join("__test_cmake_arguments");
+        let build = Build::builder()
+            .name("cmake-arguments")
+            .source(Box::new(DummyBuildSource{ path: PathBuf::from("/source") }))
+            .build_path(base_url.clone())
+            .install_prefix(PathBuf::from("/install"))
+            .library_type(LibraryType::Static)
+            .compiler_cache(None)
+            .build()
+            .expect("failed to create build");
+
+        let cmake = CMakeBuild::builder()
+            .cmake_option("BUILD_TESTING", "OFF")
+            .generator(Some("Ninja".to_owned()))
+            .build();
+
+        let arguments = cmake.configure_arguments(&build);
+        assert_eq!(arguments[0..4], ["-S", "/source", "-B", build.build_path().to_str().unwrap()]);
+        assert!(arguments.windows(2).any(|args| args == ["-G", "Ninja"]));
+        assert!(arguments.contains(&"-DCMAKE_INSTALL_PREFIX=/install".to_owned()));
+        assert!(arguments.contains(&"-DBUILD_SHARED_LIBS=OFF".to_owned()));
+        assert!(arguments.contains(&"-DBUILD_TESTING=OFF".to_owned()));
+        assert!(!arguments.iter().any(|argument| argument.starts_with("-DCMAKE_C_COMPILER_LAUNCHER=")));
+
+        #[cfg(unix)]
+        {
+            let build = Build::builder()
+                .name("cmake-arguments")
+                .source(Box::new(DummyBuildSource{ path: PathBuf::from("/source") }))
+                .build_path(base_url.clone())
+                .compiler_cache(Some(CompilerCache::Custom(PathBuf::from("/bin/sh"))))
+                .build()
+                .expect("failed to create build");
+
+            let arguments = cmake.configure_arguments(&build);
+            assert!(arguments.contains(&"-DCMAKE_C_COMPILER_LAUNCHER=/bin/sh".to_owned()));
+            assert!(arguments.contains(&"-DCMAKE_CXX_COMPILER_LAUNCHER=/bin/sh".to_owned()));
+        }
+    }
+}
@@ -0,0 +1,228 @@
+use crate::BuildStep;
+use crate::util::execute_build_command;
+use std::process::Command;
+use crate::build::{BuildResult, Build, BuildStepError, LibraryType, LinkSearchKind};
+use crate::build::toolchain::Toolchain;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::hash::{Hasher, Hash};
+
+/// A build step which executes a custom program.
+///
+/// The arguments, environment variables, working directory and library paths support the following placeholders:
+/// - `{source_dir}`: The local directory of the build source
+/// - `{build_path}`: The build path of the build
+/// - `{install_prefix}`: The install prefix of the build
+pub struct CommandStep {
+    name: String,
+    program: String,
+    arguments: Vec<String>,
+    environment: BTreeMap<String, String>,
+    working_directory: Option<String>,
+
+    libraries: Vec<(String, Option<LibraryType>)>,
+    library_paths: Vec<String>
+}
+
+impl CommandStep {
+    pub fn builder<P>(program: P) -> CommandStepBuilder
+        where P: Into<String>
+    {
+        CommandStepBuilder::new(program.into())
+    }
+
+    fn expand_placeholders(value: &str, build: &Build) -> String {
+        value.replace("{source_dir}", &build.source().local_directory().to_string_lossy())
+            .replace("{build_path}", &build.build_path().to_string_lossy())
+            .replace("{install_prefix}", &build.install_prefix().to_string_lossy())
+    }
+}
+
+impl BuildStep for CommandStep {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn hash(&self, hasher: &mut Box<dyn Hasher>) {
+        self.program.hash(hasher);
+        self.arguments.hash(hasher);
+        self.environment.iter().for_each(|(key, value)| {
+            key.hash(hasher);
+            value.hash(hasher);
+        });
+        self.working_directory.hash(hasher);
+        self.libraries.hash(hasher);
+        self.library_paths.hash(hasher);
+        Toolchain::from_env().hash(hasher);
+    }
+
+    fn execute(&mut self, build: &Build, result: &mut BuildResult) -> Result<(), BuildStepError> {
+        let mut command = Command::new(Self::expand_placeholders(&self.program, build));
+        self.arguments.iter().for_each(|argument| {
+            command.arg(Self::expand_placeholders(argument, build));
+        });
+        /* variables set for the step take precedence over the toolchain */
+        let toolchain = Toolchain::from_env();
+        toolchain.emit_rerun_if_env_changed(result);
+        toolchain.with_compiler_launcher(build.compiler_launcher().map(PathBuf::as_path))
+            .apply(&mut command);
+        self.environment.iter().for_each(|(key, value)| {
+            command.env(key, Self::expand_placeholders(value, build));
+        });
+
+        if let Some(working_directory) = &self.working_directory {
+            command.current_dir(Self::expand_placeholders(working_directory, build));
+        } else {
+            command.current_dir(build.build_path());
+        }
+
+        execute_build_command(&mut command, format!("failed to execute {}", &self.program).as_str())?;
+
+        for path in self.library_paths.iter() {
+            result.add_library_path(PathBuf::from(Self::expand_placeholders(path, build)), Some(LinkSearchKind::Native));
+        }
+
+        for (library, kind) in self.libraries.iter() {
+            result.add_library(library.clone(), *kind);
+        }
+
+        Ok(())
+    }
+}
+
+pub struct CommandStepBuilder {
+    inner: CommandStep
+}
+
+impl CommandStepBuilder {
+    fn new(program: String) -> Self {
+        CommandStepBuilder{
+            inner: CommandStep{
+                name: "command".to_owned(),
+                program,
+                arguments: Vec::new(),
+                environment: BTreeMap::new(),
+                working_directory: None,
+
+                libraries: Vec::new(),
+                library_paths: Vec::new()
+            }
+        }
+    }
+
+    /// Set the name of the step which will be used within error messages
+    pub fn name<V>(mut self, name: V) -> Self
+        where V: Into<String>
+    {
+        self.inner.name = name.into();
+        self
+    }
+
+    pub fn arg<V>(mut self, argument: V) -> Self
+        where V: Into<String>
+    {
+        self.inner.arguments.push(argument.into());
+        self
+    }
+
+    pub fn args<I, V>(mut self, arguments: I) -> Self
+        where I: IntoIterator<Item = V>,
+              V: Into<String>
+    {
+        self.inner.arguments.extend(arguments.into_iter().map(|argument| argument.into()));
+        self
+    }
+
+    pub fn env<K, V>(mut self, key: K, value: V) -> Self
+        where K: Into<String>,
+              V: Into<String>
+    {
+        self.inner.environment.insert(key.into(), value.into());
+        self
+    }
+
+    /// Set the working directory of the command.
+    /// If not set, the command will be executed within the build path.
+    pub fn working_directory(mut self, path: Option<String>) -> Self {
+        self.inner.working_directory = path;
+        self
+    }
+
+    /// Declare a library which will be produced by the command
+    pub fn library<V>(mut self, name: V, kind: Option<LibraryType>) -> Self
+        where V: Into<String>
+    {
+        self.inner.libraries.push((name.into(), kind));
+        self
+    }
+
+    /// Declare a native library search path which will contain the produced libraries
+    pub fn library_path<V>(mut self, path: V) -> Self
+        where V: Into<String>
+    {
+        self.inner.library_paths.push(path.into());
+        self
+    }
+
+    pub fn build(self) -> CommandStep {
+        self.inner
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::build::{CommandStep, LibraryType};
+    use crate::source::test::DummyBuildSource;
+    use crate::Build;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_command_step() {
+        let base_url = std::env::temp_dir().join("__test_command_step");
+        let _ = std::fs::remove_dir_all(&base_url);
+        std::fs::create_dir_all(base_url.join("source")).unwrap();
+
+        let step = CommandStep::builder("sh")
+            .name("generate library")
+            .args(vec!["-c", "mkdir -p \"$TARGET\" && touch \"$TARGET/libanswer.a\" && test \"$(pwd)\" = \"$1\"", "sh", "{source_dir}"])
+            .env("TARGET", "{install_prefix}/lib")
+            .working_directory(Some("{source_dir}".to_owned()))
+            .library("answer", Some(LibraryType::Static))
+            .library_path("{install_prefix}/lib")
+            .build();
+
+        let mut build = Build::builder()
+            .name("command-test")
+            .source(Box::new(DummyBuildSource{ path: base_url.join("source") }))
+            .add_step(Box::new(step))
+            .build_path(base_url.clone())
+            .install_prefix(base_url.join("install_root"))
+            .build()
+            .expect("failed to create build");
+
+        let result = match build.execute() {
+            Err(error) => panic!("{}", error.pretty_format()),
+            Ok(result) => result
+        };
+
+        assert!(base_url.join("install_root/lib/libanswer.a").is_file());
+        assert_eq!(result.libraries().iter().map(|library| library.to_string()).collect::<Vec<_>>(), vec!["static=answer"]);
+        assert_eq!(result.library_paths().iter().map(|path| path.to_string()).collect::<Vec<_>>(),
+                   vec![format!("native={}/lib", base_url.join("install_root").to_string_lossy())]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_command_step_failure() {
+        let mut build = Build::builder()
+            .name("command-failure-test")
+            .source(Box::new(DummyBuildSource{ path: std::env::temp_dir() }))
+            .add_step(Box::new(CommandStep::builder("false").name("failing command").build()))
+            .build_path(std::env::temp_dir().join("__test_command_step_failure"))
+            .build()
+            .expect("failed to create build");
+
+        let error = build.execute().expect_err("build should have failed");
+        assert!(error.pretty_format().starts_with("Build step \"failing command\" errored"));
+    }
+}
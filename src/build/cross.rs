@@ -0,0 +1,149 @@
+use crate::build::toolchain::{Toolchain, is_launched_through_cache};
+use std::path::Path;
+
+/// Get the meson cpu family of a target architecture
+fn cpu_family(architecture: &str) -> &str {
+    match architecture {
+        "i386" | "i586" | "i686" | "x86" => "x86",
+        "arm64" | "aarch64" => "aarch64",
+        "powerpc" => "ppc",
+        "powerpc64" | "powerpc64le" => "ppc64",
+        "mips" | "mipsel" | "mipsisa32r6" | "mipsisa32r6el" => "mips",
+        "mips64" | "mips64el" | "mipsisa64r6" | "mipsisa64r6el" => "mips64",
+        architecture if architecture.starts_with("arm") || architecture.starts_with("thumb") => "arm",
+        architecture if architecture.starts_with("riscv64") => "riscv64",
+        architecture if architecture.starts_with("riscv32") => "riscv32",
+        architecture => architecture
+    }
+}
+
+/// Get the meson system name of a target operating system
+fn system(os: &str) -> &str {
+    match os {
+        "macos" | "ios" | "tvos" | "watchos" => "darwin",
+        os => os
+    }
+}
+
+fn big_endian(architecture: &str) -> bool {
+    matches!(architecture, "powerpc" | "powerpc64" | "mips" | "mips64" | "mipsisa32r6" | "mipsisa64r6" | "s390x" | "sparc64" | "sparc")
+}
+
+/// Quote a string for meson machine files
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+fn quote_array<'a, I>(values: I) -> String
+    where I: IntoIterator<Item = &'a str>
+{
+    format!("[{}]", values.into_iter().map(quote).collect::<Vec<_>>().join(", "))
+}
+
+/// Generate a meson cross file from the environment cargo sets for build scripts.
+/// Returns `None` if cargo isn't cross compiling.
+///
+/// The compilers, archiver and flags will be taken from the [`Toolchain`] of the target.
+/// The compilers will be launched through the compiler launcher, if given.
+pub(crate) fn meson_cross_file<F>(lookup: F, compiler_launcher: Option<&Path>) -> Option<String>
+    where F: Fn(&str) -> Option<String>
+{
+    let target = lookup("TARGET")?;
+    if Some(&target) == lookup("HOST").as_ref() {
+        return None;
+    }
+
+    let components = target.split('-').collect::<Vec<_>>();
+    let architecture = lookup("CARGO_CFG_TARGET_ARCH").unwrap_or_else(|| components[0].to_owned());
+    let target_architecture = components[0];
+    let os = lookup("CARGO_CFG_TARGET_OS").unwrap_or_else(|| {
+        components.iter()
+            .skip(1)
+            .find(|component| !matches!(**component, "unknown" | "pc" | "apple" | "none"))
+            .map(|component| if *component == "darwin" { "macos" } else { component })
+            .unwrap_or("none")
+            .to_owned()
+    });
+    let endian = match lookup("CARGO_CFG_TARGET_ENDIAN") {
+        Some(endian) => endian,
+        None if big_endian(target_architecture) => "big".to_owned(),
+        None => "little".to_owned()
+    };
+
+    /* the gcc cross toolchains are usually prefixed with the target triple without the vendor */
+    let toolchain_prefix = components.iter()
+        .filter(|component| **component != "unknown")
+        .cloned()
+        .collect::<Vec<_>>()
+        .join("-");
+
+    let toolchain = Toolchain::resolve(&lookup);
+    let c_compiler = toolchain.c_compiler().map(str::to_owned).unwrap_or_else(|| format!("{}-gcc", toolchain_prefix));
+    let cpp_compiler = toolchain.cpp_compiler().map(str::to_owned).unwrap_or_else(|| format!("{}-g++", toolchain_prefix));
+    let launcher = compiler_launcher.map(|launcher| launcher.to_string_lossy());
+    let compiler_array = |compiler: &str| {
+        let launcher = launcher.as_deref().filter(|_| !is_launched_through_cache(compiler));
+        quote_array(launcher.into_iter().chain(compiler.split_whitespace()))
+    };
+    let archiver = toolchain.archiver().map(str::to_owned).unwrap_or_else(|| format!("{}-ar", toolchain_prefix));
+    let c_flags = toolchain.variable("CFLAGS").unwrap_or_default();
+    let cpp_flags = toolchain.variable("CXXFLAGS").unwrap_or_default();
+    let linker_flags = toolchain.variable("LDFLAGS").unwrap_or_default();
+
+    let mut content = String::new();
+    content.push_str("[binaries]\n");
+    content.push_str(&format!("c = {}\n", compiler_array(&c_compiler)));
+    content.push_str(&format!("cpp = {}\n", compiler_array(&cpp_compiler)));
+    content.push_str(&format!("ar = {}\n", quote_array(archiver.split_whitespace())));
+
+    content.push_str("\n[built-in options]\n");
+    content.push_str(&format!("c_args = {}\n", quote_array(c_flags.split_whitespace())));
+    content.push_str(&format!("cpp_args = {}\n", quote_array(cpp_flags.split_whitespace())));
+    content.push_str(&format!("c_link_args = {}\n", quote_array(linker_flags.split_whitespace())));
+    content.push_str(&format!("cpp_link_args = {}\n", quote_array(linker_flags.split_whitespace())));
+
+    content.push_str("\n[properties]\n");
+    content.push_str("needs_exe_wrapper = true\n");
+
+    content.push_str("\n[host_machine]\n");
+    content.push_str(&format!("system = {}\n", quote(system(&os))));
+    content.push_str(&format!("cpu_family = {}\n", quote(cpu_family(&architecture))));
+    content.push_str(&format!("cpu = {}\n", quote(target_architecture)));
+    content.push_str(&format!("endian = {}\n", quote(&endian)));
+
+    Some(content)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::build::cross::meson_cross_file;
+    use std::path::Path;
+
+    #[test]
+    fn test_meson_cross_file() {
+        let native = |key: &str| match key {
+            "TARGET" | "HOST" => Some("x86_64-unknown-linux-gnu".to_owned()),
+            _ => None
+        };
+        assert_eq!(meson_cross_file(native, None), None);
+
+        let cross = |key: &str| match key {
+            "TARGET" => Some("powerpc-unknown-linux-gnu".to_owned()),
+            "HOST" => Some("x86_64-unknown-linux-gnu".to_owned()),
+            "CC_powerpc-unknown-linux-gnu" => Some("ccache powerpc-cc".to_owned()),
+            "CFLAGS_powerpc_unknown_linux_gnu" => Some("-O2 -DNAME='it''s'".to_owned()),
+            "TARGET_LDFLAGS" => Some("-static-libgcc".to_owned()),
+            _ => None
+        };
+        let content = meson_cross_file(cross, None).expect("expected a cross file");
+        assert!(content.contains("c = ['ccache', 'powerpc-cc']\n"));
+        assert!(content.contains("cpp = ['powerpc-linux-gnu-g++']\n"));
+        assert!(content.contains("c_args = ['-O2', '-DNAME=\\'it\\'\\'s\\'']\n"));
+        assert!(content.contains("c_link_args = ['-static-libgcc']\n"));
+
+        let content = meson_cross_file(cross, Some(Path::new("/usr/bin/sccache"))).expect("expected a cross file");
+        assert!(content.contains("c = ['ccache', 'powerpc-cc']\n"));
+        assert!(content.contains("cpp = ['/usr/bin/sccache', 'powerpc-linux-gnu-g++']\n"));
+        assert!(content.contains("system = 'linux'\ncpu_family = 'ppc'\ncpu = 'powerpc'\nendian = 'big'\n"));
+    }
+}
@@ -0,0 +1,97 @@
+use jobserver::{Client, Acquired};
+use lazy_static::lazy_static;
+use std::process::Command;
+
+lazy_static! {
+    /* the jobserver of cargo (or make) which invoked the build script, if any */
+    static ref JOBSERVER: Option<Client> = unsafe { Client::from_env() };
+}
+
+/// Get the maximal number of parallel jobs.
+/// Cargo sets `NUM_JOBS` for build scripts, otherwise the available parallelism will be used.
+fn job_limit<F>(lookup: F) -> usize
+    where F: Fn(&str) -> Option<String>
+{
+    lookup("NUM_JOBS")
+        .and_then(|jobs| jobs.trim().parse::<usize>().ok())
+        .filter(|jobs| *jobs > 0)
+        .or_else(|| std::thread::available_parallelism().ok().map(|jobs| jobs.get()))
+        .unwrap_or(1)
+}
+
+/// Job tokens reserved from the jobserver for the duration of a build command.
+/// The tokens will be returned to the jobserver when the reservation gets dropped.
+pub(crate) struct JobReservation {
+    jobs: usize,
+    _tokens: Vec<Acquired>
+}
+
+impl JobReservation {
+    /// Reserve as many jobs as currently available, limited by `NUM_JOBS`.
+    /// Tokens will only be taken without blocking, since other clients might take them concurrently.
+    pub(crate) fn acquire() -> Self {
+        Self::acquire_from(JOBSERVER.as_ref(), job_limit(|key| std::env::var(key).ok()))
+    }
+
+    fn acquire_from(jobserver: Option<&Client>, limit: usize) -> Self {
+        let jobserver = match jobserver {
+            Some(jobserver) => jobserver,
+            None => return JobReservation{ jobs: limit, _tokens: Vec::new() }
+        };
+
+        /* the build script itself already holds one implicit token */
+        let mut tokens = Vec::new();
+        while tokens.len() + 1 < limit {
+            match jobserver.try_acquire() {
+                Ok(Some(token)) => tokens.push(token),
+                /* inherited pipes can't be read without blocking, so the command has to get along with the implicit token */
+                Ok(None) | Err(_) => break
+            }
+        }
+
+        JobReservation{ jobs: tokens.len() + 1, _tokens: tokens }
+    }
+
+    /// The number of jobs the command may run in parallel
+    pub(crate) fn jobs(&self) -> usize {
+        self.jobs
+    }
+}
+
+/// Configure the parallelism of a make invocation.
+/// If a jobserver is available make will share its tokens, otherwise it will be limited to `NUM_JOBS`.
+pub(crate) fn configure_make(command: &mut Command) {
+    match JOBSERVER.as_ref() {
+        Some(jobserver) => jobserver.configure_make(command),
+        None => {
+            command.arg(format!("-j{}", job_limit(|key| std::env::var(key).ok())));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::build::jobs::{job_limit, JobReservation};
+    use jobserver::Client;
+
+    #[test]
+    fn test_job_limit() {
+        assert_eq!(job_limit(|key| if key == "NUM_JOBS" { Some("3".to_owned()) } else { None }), 3);
+        assert!(job_limit(|key| if key == "NUM_JOBS" { Some("0".to_owned()) } else { None }) >= 1);
+        assert!(job_limit(|_| None) >= 1);
+    }
+
+    #[test]
+    fn test_job_reservation() {
+        assert_eq!(JobReservation::acquire_from(None, 6).jobs(), 6);
+
+        /* the tokens of a pipe based jobserver can't be taken without blocking */
+        #[cfg(unix)]
+        {
+            let jobserver = Client::new(3).expect("failed to create jobserver");
+            let reservation = JobReservation::acquire_from(Some(&jobserver), 8);
+            assert_eq!(reservation.jobs(), 1);
+            assert_eq!(jobserver.available().expect("failed to query jobserver"), 3);
+        }
+    }
+}
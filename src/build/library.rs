@@ -0,0 +1,245 @@
+use crate::build::{LibraryType, BuildResult, LinkSearchKind};
+use std::path::{PathBuf, Path};
+
+/// Derive the link name and the library type from a library file name.
+/// Returns `None` if the file isn't a library we could link against.
+pub(crate) fn parse_library_file_name(file_name: &str) -> Option<(String, LibraryType)> {
+    /* versioned shared libraries like libnice.so.10 or libnice.so.10.11.0 */
+    if let Some(index) = file_name.find(".so.") {
+        let version = &file_name[index + 4..];
+        if version.split('.').all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit())) {
+            return file_name[..index].strip_prefix("lib").map(|name| (name.to_owned(), LibraryType::Shared));
+        }
+    }
+
+    let (stem, extension) = match file_name.rfind('.') {
+        Some(index) => (&file_name[..index], &file_name[index + 1..]),
+        None => return None
+    };
+
+    match extension {
+        "a" => {
+            /* .a static libraries should all have the pattern lib<name>.a */
+            stem.strip_prefix("lib").map(|name| (name.to_owned(), LibraryType::Static))
+        },
+        "lib" => {
+            /* <name>.lib */
+            Some((stem.to_owned(), LibraryType::Static))
+        },
+        "so" | "dylib" => {
+            /* shared libraries should all have the pattern lib<name>.so */
+            stem.strip_prefix("lib").map(|name| (name.to_owned(), LibraryType::Shared))
+        },
+        _ => None
+    }
+}
+
+/// Follow a symlink chain (e.g. libnice.so -> libnice.so.10 -> libnice.so.10.11.0) to the actual file.
+/// Returns `None` if the chain is broken or recursive.
+fn resolve_symlink_chain(path: &Path) -> Option<PathBuf> {
+    let mut current = path.to_owned();
+    for _ in 0..32 {
+        let metadata = std::fs::symlink_metadata(&current).ok()?;
+        if !metadata.file_type().is_symlink() {
+            return Some(current);
+        }
+
+        let target = std::fs::read_link(&current).ok()?;
+        current = match current.parent() {
+            Some(parent) => parent.join(target),
+            None => target
+        };
+    }
+
+    None
+}
+
+/// A library which has been installed by a build step
+pub(crate) struct InstalledLibrary {
+    pub name: String,
+    pub kind: LibraryType,
+    pub path: PathBuf
+}
+
+impl InstalledLibrary {
+    /// Create a new installed library by its file.
+    /// Returns `None` if the file isn't a library or a symlink which points nowhere.
+    pub(crate) fn from_file(path: PathBuf) -> Option<Self> {
+        let file_name = path.file_name()?.to_string_lossy().into_owned();
+        let (name, mut kind) = parse_library_file_name(&file_name)?;
+
+        if std::fs::symlink_metadata(&path).is_ok() {
+            /* the file has been installed, so let the actual library decide what it is */
+            let target = resolve_symlink_chain(&path)?;
+            if let Some((target_name, target_kind)) = target.file_name().and_then(|name| parse_library_file_name(&name.to_string_lossy())) {
+                if target_name == name {
+                    kind = target_kind;
+                }
+            }
+        }
+
+        Some(InstalledLibrary{ name, kind, path })
+    }
+
+    /// Test whatever the linker would be able to find the library by its name (`-l<name>`).
+    /// Versioned shared libraries (libnice.so.10) require an unversioned symlink (libnice.so).
+    fn linkable_by_name(&self) -> bool {
+        let file_name = self.path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        if self.kind != LibraryType::Shared || file_name.ends_with(".so") || file_name.ends_with(".dylib") {
+            return true;
+        }
+
+        match self.path.parent() {
+            Some(parent) => {
+                let link_name = parent.join(format!("lib{}.so", self.name));
+                resolve_symlink_chain(&link_name).map(|target| target.exists()).unwrap_or(false)
+            },
+            None => false
+        }
+    }
+}
+
+/// Register all installed libraries to the build result.
+/// If a library has been installed as static and shared library, the `preferred_type` will be used.
+/// The library directories will be added as native link search paths.
+pub(crate) fn register_installed_libraries<I>(libraries: I, preferred_type: LibraryType, result: &mut BuildResult)
+    where I: IntoIterator<Item = InstalledLibrary>
+{
+    /* group all installed files by their library name, but keep the install order */
+    let mut installed_libraries = Vec::<(String, Vec<InstalledLibrary>)>::new();
+    for library in libraries {
+        match installed_libraries.iter_mut().find(|(name, _)| *name == library.name) {
+            Some((_, variants)) => variants.push(library),
+            None => installed_libraries.push((library.name.clone(), vec![library]))
+        }
+    }
+
+    let mut library_paths = Vec::<PathBuf>::new();
+    for (name, variants) in installed_libraries {
+        let kind = if variants.iter().any(|library| library.kind == preferred_type) {
+            preferred_type
+        } else {
+            variants[0].kind
+        };
+
+        let variants = variants.iter()
+            .filter(|library| library.kind == kind)
+            .collect::<Vec<_>>();
+
+        if !variants.iter().any(|library| library.linkable_by_name()) {
+            eprintln!("Library {} has been installed without an unversioned link name. Linking against it will most likely fail.", &name);
+        }
+
+        for library in variants {
+            if let Some(parent) = library.path.parent() {
+                if !library_paths.iter().any(|path| path == parent) {
+                    library_paths.push(parent.to_owned());
+                }
+            }
+        }
+
+        result.add_library(name, Some(kind));
+    }
+
+    for path in library_paths {
+        result.add_library_path(path, Some(LinkSearchKind::Native));
+    }
+}
+
+/// List all files (including symlinks) within a directory tree, relative to the given directory.
+/// A non existing directory results in an empty list.
+pub(crate) fn list_files(path: &Path) -> std::io::Result<Vec<PathBuf>> {
+    fn collect_files(path: &Path, relative_path: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+        for entry in std::fs::read_dir(path)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                collect_files(&entry.path(), &relative_path.join(entry.file_name()), files)?;
+            } else {
+                files.push(relative_path.join(entry.file_name()));
+            }
+        }
+        Ok(())
+    }
+
+    let mut files = Vec::new();
+    if path.is_dir() {
+        collect_files(path, Path::new(""), &mut files)?;
+    }
+    files.sort();
+    Ok(files)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::build::library::{parse_library_file_name, list_files, InstalledLibrary, register_installed_libraries};
+    use std::path::PathBuf;
+    use crate::build::{LibraryType, BuildResult};
+
+    #[test]
+    fn test_parse_library_file_name() {
+        assert!(parse_library_file_name("libsrtp2.a") == Some(("srtp2".to_owned(), LibraryType::Static)));
+        assert!(parse_library_file_name("srtp2.lib") == Some(("srtp2".to_owned(), LibraryType::Static)));
+        assert!(parse_library_file_name("libnice.so") == Some(("nice".to_owned(), LibraryType::Shared)));
+        assert!(parse_library_file_name("libnice.dylib") == Some(("nice".to_owned(), LibraryType::Shared)));
+        assert!(parse_library_file_name("libnice.so.10") == Some(("nice".to_owned(), LibraryType::Shared)));
+        assert!(parse_library_file_name("libnice.so.10.11.0") == Some(("nice".to_owned(), LibraryType::Shared)));
+        assert!(parse_library_file_name("libglib-2.0.so.0.6400.2") == Some(("glib-2.0".to_owned(), LibraryType::Shared)));
+        assert!(parse_library_file_name("libglib-2.0.so") == Some(("glib-2.0".to_owned(), LibraryType::Shared)));
+        assert!(parse_library_file_name("libnice.so.10.py").is_none());
+        assert!(parse_library_file_name("libnice.so.").is_none());
+        assert!(parse_library_file_name("nice.a").is_none());
+        assert!(parse_library_file_name("libnice.la").is_none());
+        assert!(parse_library_file_name("README").is_none());
+    }
+
+    #[test]
+    fn test_list_files() {
+        let base_path = std::env::temp_dir().join("__test_list_files");
+        let _ = std::fs::remove_dir_all(&base_path);
+        std::fs::create_dir_all(base_path.join("lib").join("pkgconfig")).unwrap();
+        std::fs::write(base_path.join("lib/libanswer.a"), "").unwrap();
+        std::fs::write(base_path.join("lib/pkgconfig/answer.pc"), "").unwrap();
+
+        assert_eq!(list_files(&base_path).unwrap(), vec![PathBuf::from("lib/libanswer.a"), PathBuf::from("lib/pkgconfig/answer.pc")]);
+        assert!(list_files(&base_path.join("missing")).unwrap().is_empty());
+
+        std::fs::remove_dir_all(&base_path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_register_symlink_chain() {
+        let base_path = std::env::temp_dir().join("__test_register_symlink_chain");
+        let _ = std::fs::remove_dir_all(&base_path);
+        std::fs::create_dir_all(&base_path).unwrap();
+
+        std::fs::write(base_path.join("libnice.so.10.11.0"), "").unwrap();
+        std::os::unix::fs::symlink("libnice.so.10.11.0", base_path.join("libnice.so.10")).unwrap();
+        std::os::unix::fs::symlink("libnice.so.10", base_path.join("libnice.so")).unwrap();
+        std::fs::write(base_path.join("libnice.a"), "").unwrap();
+        std::os::unix::fs::symlink("libmissing.so.1", base_path.join("libmissing.so")).unwrap();
+
+        let files = ["libnice.so", "libnice.so.10", "libnice.so.10.11.0", "libnice.a", "libmissing.so"];
+        let libraries = files.iter()
+            .map(|file| base_path.join(file))
+            .filter_map(InstalledLibrary::from_file)
+            .collect::<Vec<_>>();
+        assert_eq!(libraries.len(), 4);
+        assert!(libraries.iter().all(|library| library.linkable_by_name()));
+
+        let mut result = BuildResult::new();
+        register_installed_libraries(libraries, LibraryType::Shared, &mut result);
+        assert_eq!(result.libraries().iter().map(|library| library.to_string()).collect::<Vec<_>>(), vec!["dylib=nice"]);
+        assert_eq!(result.library_paths().len(), 1);
+
+        let libraries = files.iter()
+            .map(|file| base_path.join(file))
+            .filter_map(InstalledLibrary::from_file);
+
+        let mut result = BuildResult::new();
+        register_installed_libraries(libraries, LibraryType::Static, &mut result);
+        assert_eq!(result.libraries().iter().map(|library| library.to_string()).collect::<Vec<_>>(), vec!["static=nice"]);
+
+        std::fs::remove_dir_all(&base_path).unwrap();
+    }
+}
@@ -1,20 +1,135 @@
 use crate::BuildStep;
 use crate::util::execute_build_command;
 use std::process::Command;
-use crate::build::{BuildResult, Build, BuildStepError, LibraryType, LinkSearchKind};
-use std::collections::HashMap;
-use std::path::PathBuf;
+use crate::build::{BuildResult, Build, BuildStepError, LibraryType};
+use crate::build::library::{register_installed_libraries, parse_library_file_name, InstalledLibrary};
+use crate::build::cross::meson_cross_file;
+use crate::build::toolchain::Toolchain;
+use crate::build::jobs::JobReservation;
+use std::collections::BTreeMap;
+use std::path::{PathBuf, Path};
 use std::hash::{Hasher, Hash};
+use serde_json::Value;
 
 pub struct MesonBuild {
     callback_promote: Option<Box<dyn Fn(&str) -> Vec<String>>>,
-    meson_options: HashMap<String, String>
+    meson_options: BTreeMap<String, String>,
+    cross_file: Option<PathBuf>,
+    native_file: Option<PathBuf>
 }
 
 impl MesonBuild {
     pub fn builder() -> MesonBuildBuilder {
         MesonBuildBuilder::new()
     }
+
+    /// Get the meson options of the build.
+    /// Options derived from cargo's environment will be overridden by the options set by the user.
+    fn configure_options<F>(&self, lookup: F) -> BTreeMap<String, String>
+        where F: Fn(&str) -> Option<String>
+    {
+        let mut options = cargo_profile_options(lookup);
+        if self.meson_options.contains_key("buildtype") {
+            /* the build type implies the optimization and debug options, which would conflict with the derived ones */
+            options.remove("optimization");
+            options.remove("debug");
+        }
+        options.extend(self.meson_options.iter().map(|(key, value)| (key.clone(), value.clone())));
+        options
+    }
+
+    /// Get the cross file for the build.
+    /// If no cross file has been set and cargo is cross compiling, a cross file will be generated within the build path.
+    fn prepare_cross_file<F>(&self, build_path: &Path, compiler_launcher: Option<&Path>, lookup: F) -> Result<Option<PathBuf>, BuildStepError>
+        where F: Fn(&str) -> Option<String>
+    {
+        if let Some(cross_file) = &self.cross_file {
+            return Ok(Some(cross_file.clone()));
+        }
+
+        match meson_cross_file(lookup, compiler_launcher) {
+            Some(content) => {
+                let cross_file = build_path.join("rbuild-cross.ini");
+                std::fs::write(&cross_file, content)
+                    .map_err(|err| BuildStepError::new_io("failed to write meson cross file", err))?;
+                Ok(Some(cross_file))
+            },
+            None => Ok(None)
+        }
+    }
+}
+
+/// Derive the meson build type and options from the environment cargo sets for build scripts
+/// Test if rustc performs cross language LTO (`-C linker-plugin-lto`), so it can link the LTO objects of the compiler.
+/// The flags within `CARGO_ENCODED_RUSTFLAGS` are separated by `0x1f`.
+fn linker_plugin_lto_enabled(encoded_rustflags: &str) -> bool {
+    let mut enabled = false;
+    let mut codegen_option = false;
+    for flag in encoded_rustflags.split('\x1f') {
+        let option = if codegen_option {
+            codegen_option = false;
+            flag
+        } else if flag == "-C" || flag == "--codegen" {
+            codegen_option = true;
+            continue;
+        } else if let Some(option) = flag.strip_prefix("-C").or_else(|| flag.strip_prefix("--codegen=")) {
+            option
+        } else {
+            continue;
+        };
+
+        /* the last occurrence wins, a value is either a boolean or the path to the linker plugin */
+        match option.split_once('=') {
+            Some(("linker-plugin-lto", value)) => enabled = !matches!(value, "n" | "no" | "off" | "false"),
+            None if option == "linker-plugin-lto" => enabled = true,
+            _ => {}
+        }
+    }
+    enabled
+}
+
+fn cargo_profile_options<F>(lookup: F) -> BTreeMap<String, String>
+    where F: Fn(&str) -> Option<String>
+{
+    let mut options = BTreeMap::new();
+    let debug = lookup("DEBUG").map(|debug| !matches!(debug.as_str(), "false" | "0" | "none"));
+
+    match lookup("PROFILE").as_deref() {
+        Some("release") if debug == Some(true) => options.insert("buildtype".to_owned(), "debugoptimized".to_owned()),
+        Some("release") => options.insert("buildtype".to_owned(), "release".to_owned()),
+        Some("debug") => options.insert("buildtype".to_owned(), "debug".to_owned()),
+        _ => None
+    };
+
+    if let Some(level) = lookup("OPT_LEVEL") {
+        /* meson doesn't know about optimizing for size without loop vectorization */
+        let level = if level == "z" { "s".to_owned() } else { level };
+        options.insert("optimization".to_owned(), level);
+    }
+
+    if let Some(debug) = debug {
+        options.insert("debug".to_owned(), debug.to_string());
+    }
+
+    if lookup("CARGO_CFG_DEBUG_ASSERTIONS").is_some() {
+        options.insert("b_ndebug".to_owned(), "false".to_owned());
+    } else if lookup("PROFILE").is_some() {
+        options.insert("b_ndebug".to_owned(), "true".to_owned());
+    }
+
+    /* the objects will be linked by rustc, which can only handle the compilers LTO objects with the linker plugin */
+    if lookup("CARGO_ENCODED_RUSTFLAGS").map(|flags| linker_plugin_lto_enabled(&flags)).unwrap_or(false) {
+        options.insert("b_lto".to_owned(), "true".to_owned());
+    }
+
+    if let Some(target) = lookup("TARGET") {
+        /* rust executables are position independent, so are the static libraries linked into them */
+        if !target.contains("windows") {
+            options.insert("b_staticpic".to_owned(), "true".to_owned());
+        }
+    }
+
+    options
 }
 
 impl BuildStep for MesonBuild {
@@ -23,15 +138,29 @@ impl BuildStep for MesonBuild {
     }
 
     fn hash(&self, hasher: &mut Box<dyn Hasher>) {
-        self.meson_options.iter().for_each(|(key, value)| {
+        self.configure_options(|key| std::env::var(key).ok()).iter().for_each(|(key, value)| {
             key.hash(hasher);
             value.hash(hasher);
         });
+        self.cross_file.hash(hasher);
+        self.native_file.hash(hasher);
+        meson_cross_file(|key| std::env::var(key).ok(), None).hash(hasher);
+        Toolchain::from_env().hash(hasher);
     }
 
     fn execute(&mut self, build: &Build, result: &mut BuildResult) -> Result<(), BuildStepError> {
         let build_path = build.build_path().to_str().expect("invalid build path");
         let source_path = build.source().local_directory().to_str().expect("invalid source path");
+        let compiler_launcher = build.compiler_launcher().map(PathBuf::as_path);
+        let cross_file = self.prepare_cross_file(build.build_path(), compiler_launcher, |key| std::env::var(key).ok())?;
+        /* the cross file is generated from the same toolchain variables */
+        Toolchain::from_env().emit_rerun_if_env_changed(result);
+        /* meson takes the toolchain variables for the build machine when cross compiling, the host machine is defined by the cross file */
+        let toolchain = if cross_file.is_none() {
+            Toolchain::from_env().with_compiler_launcher(compiler_launcher)
+        } else {
+            Toolchain::default()
+        };
 
         let mut execute_setup = true;
         /* setup */
@@ -39,6 +168,7 @@ impl BuildStep for MesonBuild {
             execute_setup = false;
 
             let mut command = Command::new("meson");
+            toolchain.apply(&mut command);
             command.arg("setup");
             command.args(&["--prefix", build.install_prefix().to_str().expect("invalid install prefix")]);
 
@@ -47,10 +177,18 @@ impl BuildStep for MesonBuild {
                 LibraryType::Static => command.arg("-Ddefault_library=static"),
             };
 
-            self.meson_options.iter().for_each(|(key, value)| {
+            self.configure_options(|key| std::env::var(key).ok()).iter().for_each(|(key, value)| {
                 command.arg(format!("-D{}={}", key, value));
             });
 
+            if let Some(cross_file) = &cross_file {
+                command.arg("--cross-file").arg(cross_file);
+            }
+
+            if let Some(native_file) = &self.native_file {
+                command.arg("--native-file").arg(native_file);
+            }
+
             command.arg(&build_path);
             command.arg(&source_path);
 
@@ -64,6 +202,7 @@ impl BuildStep for MesonBuild {
                             for file in promote.iter() {
                                 println!("Promoting wrap file {}", file);
                                 let mut command = Command::new("meson");
+                                toolchain.apply(&mut command);
                                 command.current_dir(source_path)
                                     .arg("wrap")
                                     .arg("promote")
@@ -86,115 +225,103 @@ impl BuildStep for MesonBuild {
         /* compile */
         {
             let mut command = Command::new("ninja");
+            toolchain.apply(&mut command);
             command.arg("-C");
             command.arg(&build_path);
+
+            /* ninja would use all cpus, even if cargo is building other crates in parallel */
+            let reservation = JobReservation::acquire();
+            command.arg("-j").arg(reservation.jobs().to_string());
             execute_build_command(&mut command, "failed to build")?;
         }
 
         /* install */
         {
             let mut command = Command::new("meson");
+            toolchain.apply(&mut command);
             command.arg("install");
             command.arg("-C");
             command.arg(&build_path);
-            let (stdout, stderr) = execute_build_command(&mut command, "failed to install build")?;
+            execute_build_command(&mut command, "failed to install build")?;
+        }
 
-            let install_lines = stdout.lines()
-                .filter(|line| line.starts_with("Installing "));
+        /* Gather installed libraries and emit them to the build result */
+        {
+            let mut command = Command::new("meson");
+            toolchain.apply(&mut command);
+            command.arg("introspect");
+            command.arg(build_path);
+            command.args(["--targets", "--installed"]);
+            let (stdout, stderr) = execute_build_command(&mut command, "failed to introspect build")?;
+
+            let libraries = match parse_installed_libraries(&stdout) {
+                Ok(libraries) => libraries,
+                Err(error) => return Err(BuildStepError::new(format!("failed to parse meson introspection: {}", error), stdout, stderr))
+            };
 
-            let mut installed_elements = HashMap::with_capacity(50);
-            for full_line in install_lines {
-                /* cut of the "Installing " part */
-                let line = &full_line[11..];
-                let mut elements = line.split(" to ");
+            register_installed_libraries(libraries, build.library_type(), result);
+        }
 
-                let key = elements.next().map(|e| e.to_owned());
-                let value = elements.next().map(|e| e.to_owned());
-                if elements.next().is_some() {
-                    //return Err(BuildStepError::new(format!("Meson line \"{}\" contains more than one \" to \" parts.", full_line).to_owned(), stdout, stderr));
+        Ok(())
+    }
+}
 
-                    eprintln!("Meson line \"{}\" contains more than one \" to \" parts.", full_line);
-                    continue;
-                }
-                if key.is_none() || value.is_none() {
-                    return Err(BuildStepError::new(format!("Meson line \"{}\" misses the key or value.", full_line).to_owned(), stdout, stderr));
-                }
+/// Parse the result of `meson introspect --targets --installed` and
+/// collect all installed libraries with their final install location.
+fn parse_installed_libraries(introspection: &str) -> Result<Vec<InstalledLibrary>, String> {
+    let introspection: Value = serde_json::from_str(introspection)
+        .map_err(|error| format!("invalid json: {}", error))?;
+
+    let installed = introspection.get("installed")
+        .and_then(|installed| installed.as_object())
+        .ok_or_else(|| "missing installed files".to_owned())?;
+
+    let targets = introspection.get("targets")
+        .and_then(|targets| targets.as_array())
+        .ok_or_else(|| "missing targets".to_owned())?;
+
+    let mut libraries = Vec::new();
+    for target in targets {
+        let kind = match target.get("type").and_then(|kind| kind.as_str()) {
+            Some("static library") => LibraryType::Static,
+            Some("shared library") => LibraryType::Shared,
+            /* executables, shared modules etc. can't be linked */
+            _ => continue
+        };
+
+        if !target.get("installed").and_then(|installed| installed.as_bool()).unwrap_or(false) {
+            continue;
+        }
 
-                installed_elements.insert(key.unwrap(), value.unwrap());
-            }
+        let file_names = target.get("filename").and_then(|names| names.as_array())
+            .ok_or_else(|| format!("target {} is missing its file names", target.get("name").unwrap_or(&Value::Null)))?;
+        let install_file_names = target.get("install_filename").and_then(|names| names.as_array());
 
-            /* Gather installed libraries and emit them to the build result */
-            //println!("Stdout:\n{}\nStderr:\n{}", stdout.replace("\\", "/"), stderr);
-            let libraries = installed_elements.iter().filter_map(|(key, value)| {
-                let source = PathBuf::from(key);
-                if let Some(extension) = source.extension().map(|e| e.to_string_lossy().into_owned()) {
-                    let target = PathBuf::from(value);
-                    if !target.is_dir() {
-                        eprintln!("meson printed install for file \"{:?}\" to \"{:?}\", but target isn't a directory.", source, target);
-                        return None;
-                    }
+        for (index, file_name) in file_names.iter().enumerate() {
+            let file_name = file_name.as_str().ok_or_else(|| "target file name isn't a string".to_owned())?;
 
-                    //println!("Installed {:?} ({}) to {:?}", source, extension, target);
-                    let file_name = source.file_name().expect("missing source file name").to_string_lossy().to_string();
-                    let libname = source.file_stem().expect("missing file stem").to_string_lossy().to_string();
-                    let info = match extension.as_ref() {
-                        "a" => {
-                            if libname.starts_with("lib") {
-                                Some((libname[3..].to_owned(), LibraryType::Static))
-                            } else {
-                                /* .a static libraries should all have the pattern lib<name>.a */
-                                None
-                            }
-                        },
-                        "lib" => {
-                            /* <name>.lib */
-                            Some((libname.clone(), LibraryType::Static))
-                        },
-                        /* FIXME: Whats with libnice.so.10 as an example? */
-                        "so" => {
-                            if libname.starts_with("lib") {
-                                Some((libname[3..].to_owned(), LibraryType::Static))
-                            } else {
-                                /* .so static libraries should all have the pattern lib<name>.so */
-                                None
-                            }
-                        },
-                        "dll" => {
-                            /* <name>.dll */
-                            // We can't link dll files?
-                            //Some((libname.clone(), LibraryType::Shared))
-                            None
-                        },
-                        _ => None
-                    };
-
-                    if let Some((libname, kind)) = info {
-                        result.add_library_path(target, Some(LinkSearchKind::Native));
-                        Some((file_name, libname, kind))
-                    } else {
-                        None
-                    }
-                } else {
-                    None
-                }
-            }).collect::<Vec<_>>();
-
-            for (file_name, library, kind) in libraries.iter() {
-                /*
-                if libraries.iter().find(|(fname, lname, _)| *lname == *library && *fname != *file_name).is_some() {
-                    /* we've a static and a shared instance of that library */
-                    result.add_library(file_name.clone(), Some(kind.clone()));
-                } else {
-                    /* Don't specify the kind so rust will link the library in static and shared builds */
-                    result.add_library(file_name.clone(), None);
-                }
-                */
-                result.add_library(library.clone(), None);
+            /* older meson versions don't provide the install_filename attribute */
+            let install_path = installed.get(file_name)
+                .or_else(|| install_file_names.and_then(|names| names.get(index)))
+                .and_then(|path| path.as_str());
+
+            let install_path = match install_path {
+                Some(path) => PathBuf::from(path),
+                None => continue
+            };
+
+            let install_file_name = match install_path.file_name() {
+                Some(name) => name.to_string_lossy().into_owned(),
+                None => continue
+            };
+
+            if let Some((name, _)) = parse_library_file_name(&install_file_name) {
+                libraries.push(InstalledLibrary{ name, kind, path: install_path });
             }
         }
-
-        Ok(())
     }
+
+    Ok(libraries)
 }
 
 pub struct MesonBuildBuilder {
@@ -206,11 +333,16 @@ impl MesonBuildBuilder {
         MesonBuildBuilder{
             inner: MesonBuild{
                 callback_promote: None,
-                meson_options: HashMap::new()
+                meson_options: BTreeMap::new(),
+                cross_file: None,
+                native_file: None
             }
         }
     }
 
+    /// Set a meson option (`-D<key>=<value>`).
+    /// Overrides the options derived from cargo (`buildtype`, `optimization`, `debug`, `b_ndebug`, `b_lto` and `b_staticpic`).
+    /// Setting `buildtype` drops the derived `optimization` and `debug` options.
     pub fn meson_option<K, V>(mut self, key: K, value: V) -> Self
         where K: Into<String>,
               V: Into<String>
@@ -219,6 +351,17 @@ impl MesonBuildBuilder {
         self
     }
 
+    /// Use the given cross file instead of generating one when cargo is cross compiling
+    pub fn cross_file(mut self, path: Option<PathBuf>) -> Self {
+        self.inner.cross_file = path;
+        self
+    }
+
+    pub fn native_file(mut self, path: Option<PathBuf>) -> Self {
+        self.inner.native_file = path;
+        self
+    }
+
     pub fn promote_callback<F: 'static>(mut self, callback: F) -> Self
         where F: Fn(&str) -> Vec<String>
     {
@@ -233,7 +376,11 @@ impl MesonBuildBuilder {
 
 #[cfg(test)]
 mod test {
-    use crate::build::{BuildBuilder, MesonBuild};
+    use crate::build::{BuildBuilder, MesonBuild, LibraryType};
+    use crate::build::meson::{parse_installed_libraries, cargo_profile_options, linker_plugin_lto_enabled};
+    use std::process::Command;
+    use std::collections::BTreeMap;
+    use std::path::PathBuf;
     use crate::source::BuildSourceGit;
     use std::env;
     use crate::Build;
@@ -311,4 +458,155 @@ mod test {
             Ok(result) => result.emit_cargo()
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_parse_installed_libraries() {
+        let introspection = r#"{
+            "targets": [
+                {
+                    "name": "nice", "type": "shared library", "installed": true,
+                    "filename": ["/build/nice/libnice.so.10.11.0"],
+                    "install_filename": ["/prefix/lib/libnice.so.10.11.0"]
+                },
+                {
+                    "name": "srtp2", "type": "static library", "installed": true,
+                    "filename": ["/build/libsrtp2.a"]
+                },
+                {
+                    "name": "test-srtp", "type": "executable", "installed": true,
+                    "filename": ["/build/test-srtp"],
+                    "install_filename": ["/prefix/bin/test-srtp"]
+                },
+                {
+                    "name": "internal", "type": "static library", "installed": false,
+                    "filename": ["/build/libinternal.a"]
+                }
+            ],
+            "installed": {
+                "/build/libsrtp2.a": "/prefix/lib/x86_64-linux-gnu/libsrtp2.a",
+                "/build/test-srtp": "/prefix/bin/test-srtp"
+            }
+        }"#;
+
+        let libraries = parse_installed_libraries(introspection).expect("failed to parse introspection");
+        assert_eq!(libraries.len(), 2);
+        assert_eq!(libraries[0].name, "nice");
+        assert!(libraries[0].kind == LibraryType::Shared);
+        assert_eq!(libraries[0].path, PathBuf::from("/prefix/lib/libnice.so.10.11.0"));
+        assert_eq!(libraries[1].name, "srtp2");
+        assert!(libraries[1].kind == LibraryType::Static);
+        assert_eq!(libraries[1].path, PathBuf::from("/prefix/lib/x86_64-linux-gnu/libsrtp2.a"));
+
+        assert!(parse_installed_libraries("[]").is_err());
+    }
+
+    #[test]
+    fn test_linker_plugin_lto_enabled() {
+        assert!(!linker_plugin_lto_enabled(""));
+        assert!(!linker_plugin_lto_enabled("-Clto"));
+        assert!(linker_plugin_lto_enabled("-Clinker-plugin-lto"));
+        assert!(linker_plugin_lto_enabled("-C\x1flinker-plugin-lto=/usr/lib/LLVMgold.so"));
+        assert!(linker_plugin_lto_enabled("--codegen=linker-plugin-lto=yes"));
+        assert!(!linker_plugin_lto_enabled("-Clinker-plugin-lto\x1f-C\x1flinker-plugin-lto=no"));
+        /* a separated value doesn't belong to an option */
+        assert!(!linker_plugin_lto_enabled("--cfg\x1flinker-plugin-lto"));
+    }
+
+    #[test]
+    fn test_cargo_profile_options() {
+        let environment = |values: &[(&str, &str)]| {
+            values.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect::<BTreeMap<_, _>>()
+        };
+        let options = |environment: &BTreeMap<String, String>| {
+            cargo_profile_options(|key| environment.get(key).cloned())
+                .into_iter()
+                .map(|(key, value)| format!("{}={}", key, value))
+                .collect::<Vec<_>>()
+        };
+
+        let debug = environment(&[("PROFILE", "debug"), ("OPT_LEVEL", "0"), ("DEBUG", "true"), ("CARGO_CFG_DEBUG_ASSERTIONS", ""), ("TARGET", "x86_64-unknown-linux-gnu")]);
+        assert_eq!(options(&debug), vec!["b_ndebug=false", "b_staticpic=true", "buildtype=debug", "debug=true", "optimization=0"]);
+
+        let release = environment(&[("PROFILE", "release"), ("OPT_LEVEL", "z"), ("DEBUG", "false"), ("TARGET", "x86_64-pc-windows-msvc")]);
+        assert_eq!(options(&release), vec!["b_ndebug=true", "buildtype=release", "debug=false", "optimization=s"]);
+
+        let release_debug = environment(&[("PROFILE", "release"), ("OPT_LEVEL", "3"), ("DEBUG", "true")]);
+        assert_eq!(options(&release_debug), vec!["b_ndebug=true", "buildtype=debugoptimized", "debug=true", "optimization=3"]);
+
+        let lto = environment(&[("PROFILE", "release"), ("CARGO_ENCODED_RUSTFLAGS", "-Clinker-plugin-lto\x1f-C\x1flinker=clang")]);
+        assert_eq!(options(&lto), vec!["b_lto=true", "b_ndebug=true", "buildtype=release"]);
+
+        let meson = MesonBuild::builder()
+            .meson_option("optimization", "2")
+            .meson_option("b_lto", "true")
+            .build();
+        let configured = meson.configure_options(|key| release.get(key).cloned());
+        assert_eq!(configured.get("optimization").map(|value| value.as_str()), Some("2"));
+        assert_eq!(configured.get("b_lto").map(|value| value.as_str()), Some("true"));
+        assert_eq!(configured.get("buildtype").map(|value| value.as_str()), Some("release"));
+
+        let meson = MesonBuild::builder()
+            .meson_option("buildtype", "minsize")
+            .build();
+        let configured = meson.configure_options(|key| release.get(key).cloned());
+        assert_eq!(configured.get("buildtype").map(|value| value.as_str()), Some("minsize"));
+        assert_eq!(configured.get("optimization"), None);
+        assert_eq!(configured.get("debug"), None);
+        assert_eq!(configured.get("b_ndebug").map(|value| value.as_str()), Some("true"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_generate_cross_file() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let base_url = std::env::temp_dir().join("__test_meson_cross_file");
+        let _ = std::fs::remove_dir_all(&base_url);
+        std::fs::create_dir_all(&base_url).unwrap();
+
+        /* a compiler wrapper which just reports how it has been called */
+        let compiler = base_url.join("fake-cc");
+        std::fs::write(&compiler, "#!/bin/sh\necho \"fake-cc $@\"\n").unwrap();
+        std::fs::set_permissions(&compiler, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let environment = [
+            ("TARGET", "aarch64-unknown-linux-gnu"),
+            ("HOST", "x86_64-unknown-linux-gnu"),
+            ("CARGO_CFG_TARGET_OS", "linux"),
+            ("CARGO_CFG_TARGET_ARCH", "aarch64"),
+            ("CFLAGS_aarch64-unknown-linux-gnu", "-march=armv8-a"),
+        ].iter().map(|(key, value)| (key.to_string(), value.to_string())).collect::<BTreeMap<_, _>>();
+        let lookup = |key: &str| {
+            if key == "CC_aarch64_unknown_linux_gnu" {
+                Some(compiler.to_string_lossy().into_owned())
+            } else {
+                environment.get(key).cloned()
+            }
+        };
+
+        let meson = MesonBuild::builder().build();
+        let cross_file = meson.prepare_cross_file(&base_url, None, lookup)
+            .expect("failed to write cross file")
+            .expect("expected a cross file");
+        let content = std::fs::read_to_string(&cross_file).unwrap();
+        assert!(content.contains("c_args = ['-march=armv8-a']\n"));
+        assert!(content.contains("cpu_family = 'aarch64'\n"));
+
+        /* the compiler meson will use must be the wrapper */
+        let c_compiler = content.lines()
+            .find_map(|line| line.strip_prefix("c = ['"))
+            .and_then(|line| line.strip_suffix("']"))
+            .expect("missing c compiler");
+        let output = Command::new(c_compiler).arg("--version").output().expect("failed to execute compiler");
+        assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "fake-cc --version");
+
+        /* native builds and user supplied cross files don't require a generated cross file */
+        let native = |key: &str| if key == "TARGET" || key == "HOST" { Some("x86_64-unknown-linux-gnu".to_owned()) } else { None };
+        assert_eq!(meson.prepare_cross_file(&base_url, None, native).unwrap(), None);
+
+        let meson = MesonBuild::builder().cross_file(Some(PathBuf::from("/cross/aarch64.ini"))).build();
+        assert_eq!(meson.prepare_cross_file(&base_url, None, lookup).unwrap(), Some(PathBuf::from("/cross/aarch64.ini")));
+
+        std::fs::remove_dir_all(&base_url).unwrap();
+    }
+}
@@ -1,15 +1,37 @@
-use crate::source::BuildSource;
+use crate::source::{BuildSource, SourceSetupError};
 use std::ops::{Deref};
-use std::path::PathBuf;
+use std::path::{PathBuf, Path};
 
 mod meson;
 pub use meson::*;
-use crate::util::{TemporaryPath, create_temporary_path, install_prefix, build_library_type, BuildLibraryTypeError};
+
+mod cmake;
+pub use cmake::*;
+
+mod autotools;
+pub use autotools::*;
+
+mod command;
+pub use command::*;
+
+mod cache;
+pub use cache::CompilerCache;
+
+mod cross;
+mod jobs;
+mod toolchain;
+pub use toolchain::Toolchain;
+mod library;
+
+mod stamp;
+use stamp::StepStamp;
+use cache::print_statistics as print_compiler_cache_statistics;
+use crate::util::{TemporaryPath, create_temporary_path, install_prefix, build_library_type, compiler_cache, BuildLibraryTypeError};
 use std::cell::RefCell;
 use std::collections::hash_map::DefaultHasher;
 use std::hash::{Hash, Hasher};
 
-#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
 pub enum LibraryType {
     Static,
     Shared
@@ -24,6 +46,16 @@ impl ToString for LibraryType {
     }
 }
 
+impl LibraryType {
+    fn from_cargo_name(name: &str) -> Option<Self> {
+        match name {
+            "dylib" => Some(LibraryType::Shared),
+            "static" => Some(LibraryType::Static),
+            _ => None
+        }
+    }
+}
+
 #[derive(Ord, PartialOrd, Eq, PartialEq, Debug)]
 pub enum LinkSearchKind {
     Dependency,
@@ -45,6 +77,19 @@ impl ToString for LinkSearchKind {
     }
 }
 
+impl LinkSearchKind {
+    fn from_cargo_name(name: &str) -> Option<Self> {
+        match name {
+            "dependency" => Some(LinkSearchKind::Dependency),
+            "crate" => Some(LinkSearchKind::Crate),
+            "native" => Some(LinkSearchKind::Native),
+            "framework" => Some(LinkSearchKind::Framework),
+            "all" => Some(LinkSearchKind::All),
+            _ => None
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum BuildCreateError {
     Unknown,
@@ -73,7 +118,8 @@ pub enum BuildCreateError {
 #[derive(Debug)]
 pub struct BuildError {
     step: String,
-    error: BuildStepError
+    error: BuildStepError,
+    source_error: Option<Box<SourceSetupError>>
 }
 
 impl BuildError {
@@ -97,6 +143,11 @@ impl BuildError {
     pub fn error(&self) -> &BuildStepError {
         &self.error
     }
+
+    /// Get the typed source error if the setup of the build source failed
+    pub fn source_error(&self) -> Option<&SourceSetupError> {
+        self.source_error.as_deref()
+    }
 }
 
 impl Deref for BuildError {
@@ -107,12 +158,16 @@ impl Deref for BuildError {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct BuildStepError {
     detail: String,
 
     stdout: String,
-    stderr: String
+    stderr: String,
+
+    /* the typed cause of the failure, used to classify the error without parsing its messages */
+    io_error_kind: Option<std::io::ErrorKind>,
+    exit_code: Option<i32>
 }
 
 impl BuildStepError {
@@ -125,7 +180,16 @@ impl BuildStepError {
     pub fn new_io<S>(detail: S, error: std::io::Error) -> Self
         where S: Into<String>
     {
-        Self::new(detail.into(), String::new(), format!("IOError: {}", error.to_string()))
+        let mut result = Self::new(detail.into(), String::new(), format!("IOError: {}", error.to_string()));
+        result.io_error_kind = Some(error.kind());
+        result
+    }
+
+    /// Create an error for a command which exited with the given exit code
+    pub fn new_exit_code(detail: String, stdout: String, stderr: String, exit_code: Option<i32>) -> Self {
+        let mut result = Self::new(detail, stdout, stderr);
+        result.exit_code = exit_code;
+        result
     }
 
     pub fn new(detail: String, stdout: String, stderr: String) -> Self {
@@ -133,7 +197,10 @@ impl BuildStepError {
             detail,
 
             stdout,
-            stderr
+            stderr,
+
+            io_error_kind: None,
+            exit_code: None
         }
     }
 
@@ -144,8 +211,19 @@ impl BuildStepError {
     pub fn stderr(&self) -> &str {
         &self.stderr
     }
+
+    /// The kind of the io error which caused the failure, if any
+    pub fn io_error_kind(&self) -> Option<std::io::ErrorKind> {
+        self.io_error_kind
+    }
+
+    /// The exit code of the failed command, if any
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit_code
+    }
 }
 
+#[derive(Debug, PartialEq)]
 pub struct BuildLibrary {
     name: String,
     kind: Option<LibraryType>
@@ -161,6 +239,7 @@ impl ToString for BuildLibrary {
     }
 }
 
+#[derive(Debug, PartialEq)]
 pub struct BuildLibraryPath {
     path: PathBuf,
     kind: LinkSearchKind
@@ -176,6 +255,42 @@ impl ToString for BuildLibraryPath {
     }
 }
 
+const BUILD_RESULT_HEADER: &str = "# rbuild build result v1";
+
+fn escape_field(value: &str) -> String {
+    value.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+fn unescape_field(value: &str) -> Option<String> {
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(char) = chars.next() {
+        if char != '\\' {
+            result.push(char);
+            continue;
+        }
+
+        match chars.next()? {
+            '\\' => result.push('\\'),
+            't' => result.push('\t'),
+            'n' => result.push('\n'),
+            'r' => result.push('\r'),
+            _ => return None
+        }
+    }
+    Some(result)
+}
+
+#[derive(Debug)]
+pub enum BuildResultLoadError {
+    IOError(std::io::Error),
+    InvalidFormat(String)
+}
+
+#[derive(Debug, PartialEq)]
 pub struct BuildResult {
     libraries: Vec<BuildLibrary>,
     library_paths: Vec<BuildLibraryPath>,
@@ -215,6 +330,100 @@ impl BuildResult {
         self
     }
 
+    /// Merge all libraries, library paths and emits of another result into this result.
+    /// Library paths and emits which are already present will not be added twice.
+    /// Libraries are always appended, since repeating a static library may be required by the link order.
+    pub fn merge(&mut self, other: BuildResult) -> &mut Self {
+        self.libraries.extend(other.libraries);
+
+        for path in other.library_paths {
+            if !self.library_paths.contains(&path) {
+                self.library_paths.push(path);
+            }
+        }
+
+        for emit in other.custom_compiler_emits {
+            if !self.custom_compiler_emits.contains(&emit) {
+                self.custom_compiler_emits.push(emit);
+            }
+        }
+        self
+    }
+
+    /// Serialize the result into a stable, line based text format.
+    /// Every entry is written into its own line with tab separated fields.
+    pub fn serialize(&self) -> String {
+        let mut result = String::with_capacity(BUILD_RESULT_HEADER.len() + 1);
+        result.push_str(BUILD_RESULT_HEADER);
+        result.push('\n');
+
+        for library in self.libraries.iter() {
+            let kind = library.kind.map(|kind| kind.to_string()).unwrap_or_else(|| "-".to_owned());
+            result.push_str(&format!("library\t{}\t{}\n", kind, escape_field(&library.name)));
+        }
+
+        for path in self.library_paths.iter() {
+            result.push_str(&format!("library_path\t{}\t{}\n", path.kind.to_string(), escape_field(&path.path.to_string_lossy())));
+        }
+
+        for emit in self.custom_compiler_emits.iter() {
+            result.push_str(&format!("emit\t{}\n", escape_field(emit)));
+        }
+        result
+    }
+
+    /// Parse a result which has been serialized by `serialize`.
+    pub fn deserialize(data: &str) -> Result<Self, BuildResultLoadError> {
+        let mut lines = data.lines();
+        if lines.next() != Some(BUILD_RESULT_HEADER) {
+            return Err(BuildResultLoadError::InvalidFormat("missing or unsupported header".to_owned()));
+        }
+
+        let mut result = BuildResult::new();
+        for line in lines.filter(|line| !line.is_empty()) {
+            let invalid_line = || BuildResultLoadError::InvalidFormat(format!("invalid line \"{}\"", line));
+
+            let mut parts = line.splitn(2, '\t');
+            match (parts.next(), parts.next()) {
+                (Some("library"), Some(value)) => {
+                    let mut parts = value.splitn(2, '\t');
+                    let (kind, name) = (parts.next().ok_or_else(invalid_line)?, parts.next().ok_or_else(invalid_line)?);
+                    let kind = match kind {
+                        "-" => None,
+                        kind => Some(LibraryType::from_cargo_name(kind).ok_or_else(invalid_line)?)
+                    };
+                    result.add_library(unescape_field(name).ok_or_else(invalid_line)?, kind);
+                },
+                (Some("library_path"), Some(value)) => {
+                    let mut parts = value.splitn(2, '\t');
+                    let (kind, path) = (parts.next().ok_or_else(invalid_line)?, parts.next().ok_or_else(invalid_line)?);
+                    let kind = LinkSearchKind::from_cargo_name(kind).ok_or_else(invalid_line)?;
+                    result.add_library_path(PathBuf::from(unescape_field(path).ok_or_else(invalid_line)?), Some(kind));
+                },
+                (Some("emit"), Some(emit)) => {
+                    result.add_emit(unescape_field(emit).ok_or_else(invalid_line)?);
+                },
+                _ => return Err(invalid_line())
+            }
+        }
+        Ok(result)
+    }
+
+    /// Save the result to a file
+    pub fn save_to_file(&self, path: &Path) -> std::io::Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, self.serialize())
+    }
+
+    /// Load a result which has been saved with `save_to_file`
+    pub fn load_from_file(path: &Path) -> Result<Self, BuildResultLoadError> {
+        let data = std::fs::read_to_string(path)
+            .map_err(BuildResultLoadError::IOError)?;
+        Self::deserialize(&data)
+    }
+
     pub fn emit_cargo(&self) {
         self.library_paths.iter().for_each(|path| {
             println!("cargo:rustc-link-search={}", path.to_string());
@@ -236,10 +445,10 @@ pub trait BuildStep {
     /// Generate a hash which uniquely identifies the build options
     fn hash(&self, hasher: &mut Box<dyn Hasher>);
 
-    /* some generic function */
+    /// Execute the step.
+    /// The step will be skipped on later builds if neither the build hash nor the step hash changed.
+    /// In that case the results of the previous execution will be restored.
     fn execute(&mut self, build: &Build, result: &mut BuildResult) -> Result<(), BuildStepError>;
-
-    /* TODO: Add method to check whatever this step needs to be reapplied (Keep in mind to proper set the BuildResult) */
 }
 
 pub struct Build {
@@ -253,6 +462,8 @@ pub struct Build {
 
     build_path: TemporaryPath,
     install_prefix: PathBuf,
+
+    compiler_launcher: Option<PathBuf>,
 }
 
 impl Build {
@@ -289,25 +500,84 @@ impl Build {
         self.build_hash
     }
 
+    /// Get the compiler cache executable which should be used to launch the C/C++ compilers
+    pub fn compiler_launcher(&self) -> Option<&PathBuf> {
+        self.compiler_launcher.as_ref()
+    }
+
+    /// Get the path where the result of the last successful execution will be saved to
+    pub fn result_path(&self) -> PathBuf {
+        self.build_path().join(".rbuild").join("build_result")
+    }
+
+    /// Set up the source and fingerprint its state.
+    /// Returns `None` if the state of the source is unknown.
+    fn setup_source(&mut self) -> Result<Option<u64>, SourceSetupError> {
+        self.source.setup()?;
+
+        let mut hash: Box<dyn Hasher> = Box::new(DefaultHasher::new());
+        if !self.source.fingerprint(&mut hash)? {
+            return Ok(None);
+        }
+        Ok(Some(hash.finish()))
+    }
+
     /// Execute the build and all its steps
     pub fn execute(&mut self) -> Result<BuildResult, BuildError> {
-        if let Err(error) = self.source.setup() {
-            return Err(BuildError{
-                step: "source setup".to_owned(),
-                error
-            });
-        }
+        let source_fingerprint = match self.setup_source() {
+            Ok(fingerprint) => fingerprint,
+            Err(error) => {
+                return Err(BuildError{
+                    step: "source setup".to_owned(),
+                    error: error.error().clone(),
+                    source_error: Some(Box::new(error))
+                });
+            }
+        };
 
         let mut result = BuildResult::new();
-        for step in self.steps.iter() {
+        let mut previous_steps_skipped = true;
+        for (index, step) in self.steps.iter().enumerate() {
             let mut step = RefCell::borrow_mut(step);
+            let stamp = StepStamp::new(self, index, source_fingerprint);
+
+            /* if a previous step has been executed all following steps must be executed as well */
+            if previous_steps_skipped {
+                if let Some(step_result) = stamp.load() {
+                    println!("Skipping build step \"{}\" since it's up to date", step.name());
+                    result.merge(step_result);
+                    continue;
+                }
+                previous_steps_skipped = false;
+            }
+
+            stamp.invalidate();
 
-            if let Err(err) = step.execute(self, &mut result) {
+            let mut step_result = BuildResult::new();
+            if let Err(err) = step.execute(self, &mut step_result) {
                 return Err(BuildError{
                     step: step.name().to_owned(),
-                    error: err
+                    error: err,
+                    source_error: None
                 })
             }
+
+            if let Err(error) = stamp.store(&step_result) {
+                eprintln!("Failed to write stamp for build step \"{}\": {:?}", step.name(), error);
+            }
+            result.merge(step_result);
+        }
+
+        if let (false, Some(launcher)) = (previous_steps_skipped, &self.compiler_launcher) {
+            print_compiler_cache_statistics(launcher);
+        }
+
+        for path in self.source.rerun_if_changed() {
+            result.add_emit(format!("rerun-if-changed={}", path.to_string_lossy()));
+        }
+
+        if let Err(error) = result.save_to_file(&self.result_path()) {
+            eprintln!("Failed to save build result: {:?}", error);
         }
         Ok(result)
     }
@@ -324,7 +594,9 @@ pub struct BuildBuilder {
     build_path: Option<PathBuf>,
 
     /* TODO: Make this variable environment editable */
+    /* the build directory must be kept for the step stamps to take effect */
     remove_build_dir: bool,
+    compiler_cache: Option<Option<CompilerCache>>,
     /* TODO: Env */
 }
 
@@ -340,7 +612,8 @@ impl BuildBuilder {
             install_prefix: None,
             build_path: None,
 
-            remove_build_dir: true
+            remove_build_dir: true,
+            compiler_cache: None
         }
     }
 
@@ -404,6 +677,18 @@ impl BuildBuilder {
             build_path.release();
         }
 
+        /* the compiler cache doesn't affect the build result, so it's not part of the build hash */
+        let compiler_launcher = match self.compiler_cache.or_else(|| compiler_cache(&name)).flatten() {
+            Some(cache) => {
+                let launcher = cache.launcher();
+                if launcher.is_none() {
+                    eprintln!("Compiler cache {:?} is not available. Building without it.", cache);
+                }
+                launcher
+            },
+            None => None
+        };
+
         Ok(Box::new(Build{
             name,
             source,
@@ -413,7 +698,9 @@ impl BuildBuilder {
             library_type,
 
             build_path,
-            install_prefix
+            install_prefix,
+
+            compiler_launcher
         }))
     }
 
@@ -444,11 +731,21 @@ impl BuildBuilder {
         self
     }
 
+    /// Remove the build directory after the build (default: `true`).
+    /// Keep the build directory to skip the build steps which are still up to date on the next build,
+    /// since the step stamps are stored within it.
     pub fn remove_build_dir(mut self, enabled: bool) -> Self {
         self.remove_build_dir = enabled;
         self
     }
 
+    /// Launch the C/C++ compilers through a compiler cache like ccache or sccache.
+    /// Setting `None` disables the compiler cache, even if enabled through `rbuild_compiler_cache`.
+    pub fn compiler_cache(mut self, cache: Option<CompilerCache>) -> Self {
+        self.compiler_cache = Some(cache);
+        self
+    }
+
     pub fn add_step(mut self, step: Box<dyn BuildStep>) -> Self {
         self.steps.push(RefCell::new(step));
         self
@@ -459,10 +756,13 @@ impl BuildBuilder {
 #[cfg(test)]
 mod test {
     use crate::BuildStep;
-    use crate::build::{Build, BuildResult, BuildStepError};
-    use crate::source::{BuildSource};
+    use crate::build::{Build, BuildResult, BuildStepError, LibraryType, LinkSearchKind};
+    use crate::source::{BuildSource, SourceSetupError};
+    use crate::source::test::DummyBuildSource;
     use std::path::PathBuf;
-    use std::hash::Hasher;
+    use std::hash::{Hasher, Hash};
+    use std::rc::Rc;
+    use std::cell::Cell;
 
     struct DummyBuildStep { }
     impl BuildStep for DummyBuildStep {
@@ -477,16 +777,26 @@ mod test {
         }
     }
 
-    struct DummyBuildSource {}
-    impl BuildSource for DummyBuildSource {
+    #[test]
+    fn test_builder() {
+        let mut build = Build::builder()
+            .name("test")
+            .source(Box::new(DummyBuildSource{ path: PathBuf::new() }))
+            .add_step(Box::new(DummyBuildStep{}))
+            .build().expect("failed to create dummy build");
+        build.execute().expect("build should have succeeded");
+    }
+
+    struct FailingBuildSource {}
+    impl BuildSource for FailingBuildSource {
         fn name(&self) -> &str {
-            "dummy"
+            "failing"
         }
 
         fn hash(&self, _state: &mut Box<dyn Hasher>) { }
 
-        fn setup(&mut self) -> Result<(), BuildStepError> {
-            Ok(())
+        fn setup(&mut self) -> Result<(), SourceSetupError> {
+            Err(BuildStepError::new("git clone failed".to_owned(), String::new(), "fatal: unable to access 'https://github.com/cisco/libsrtp.git/': Could not resolve host: github.com".to_owned()).into())
         }
 
         fn local_directory(&self) -> &PathBuf {
@@ -497,12 +807,185 @@ mod test {
     }
 
     #[test]
-    fn test_builder() {
+    fn test_source_setup_error() {
         let mut build = Build::builder()
             .name("test")
-            .source(Box::new(DummyBuildSource{}))
+            .source(Box::new(FailingBuildSource{}))
             .add_step(Box::new(DummyBuildStep{}))
             .build().expect("failed to create dummy build");
-        build.execute().expect("build should have succeeded");
+
+        let error = build.execute().expect_err("build should have failed");
+        assert!(error.pretty_format().starts_with("Build step \"source setup\" errored: git clone failed"));
+        assert!(matches!(error.source_error(), Some(SourceSetupError::NetworkFailure(_))));
+        assert!(error.source_error().unwrap().is_transient());
+
+        let error: SourceSetupError = BuildStepError::new_io("failed to unpack archive", std::io::Error::from(std::io::ErrorKind::StorageFull)).into();
+        assert!(matches!(error, SourceSetupError::NoSpaceOnDisk(_)));
+        let error: SourceSetupError = BuildStepError::new_io("failed to connect", std::io::Error::from(std::io::ErrorKind::TimedOut)).into();
+        assert!(error.is_transient());
+
+        /* authentication failures and missing repositories can't be fixed by retrying */
+        let error: SourceSetupError = BuildStepError::new("git clone failed".to_owned(), String::new(), "git@github.com: Permission denied (publickey).\nfatal: Could not read from remote repository.".to_owned()).into();
+        assert!(matches!(error, SourceSetupError::AuthenticationFailed(_)));
+        assert!(!error.is_transient());
+        let error: SourceSetupError = BuildStepError::new("git clone failed".to_owned(), String::new(), "fatal: Authentication failed for 'https://github.com/private.git/'".to_owned()).into();
+        assert!(matches!(error, SourceSetupError::AuthenticationFailed(_)));
+        let error: SourceSetupError = BuildStepError::new("git clone failed".to_owned(), String::new(), "fatal: could not create work tree dir 'libnice': Permission denied".to_owned()).into();
+        assert!(matches!(error, SourceSetupError::PermissionDenied(_)));
+        let error: SourceSetupError = BuildStepError::new("git clone failed".to_owned(), String::new(), "ERROR: Repository not found.\nfatal: Could not read from remote repository.".to_owned()).into();
+        assert!(!error.is_transient());
+        let error: SourceSetupError = BuildStepError::new("git clone failed".to_owned(), String::new(), "fatal: unable to access 'https://github.com/missing.git/': The requested URL returned error: 404".to_owned()).into();
+        assert!(!error.is_transient());
+    }
+
+    struct CountingBuildStep {
+        option: String,
+        executions: Rc<Cell<usize>>
+    }
+
+    impl BuildStep for CountingBuildStep {
+        fn name(&self) -> &str {
+            "counting"
+        }
+
+        fn hash(&self, state: &mut Box<dyn Hasher>) {
+            self.option.hash(state);
+        }
+
+        fn execute(&mut self, _build: &Build, result: &mut BuildResult) -> Result<(), BuildStepError> {
+            self.executions.set(self.executions.get() + 1);
+            result.add_library("counting".to_owned(), Some(LibraryType::Static));
+            result.add_library_path(PathBuf::from("/counting/lib"), Some(LinkSearchKind::Native));
+            result.add_emit("rustc-cfg=counting".to_owned());
+            Ok(())
+        }
+    }
+
+    /// A source tracking a branch, which may resolve to another revision on every setup
+    struct RevisionBuildSource {
+        revision: String
+    }
+
+    impl BuildSource for RevisionBuildSource {
+        fn name(&self) -> &str {
+            "revision"
+        }
+
+        fn hash(&self, _state: &mut Box<dyn Hasher>) { }
+
+        fn setup(&mut self) -> Result<(), SourceSetupError> {
+            Ok(())
+        }
+
+        fn local_directory(&self) -> &PathBuf {
+            unimplemented!()
+        }
+
+        fn cleanup(&mut self) { }
+
+        fn resolved_revision(&self) -> Option<&str> {
+            Some(&self.revision)
+        }
+    }
+
+    #[test]
+    fn test_result_serialization() {
+        let mut result = BuildResult::new();
+        result.add_library("nice".to_owned(), Some(LibraryType::Shared))
+            .add_library("srtp2".to_owned(), Some(LibraryType::Static))
+            .add_library("glib-2.0".to_owned(), None)
+            .add_library_path(PathBuf::from("/install root/lib"), Some(LinkSearchKind::Native))
+            .add_library_path(PathBuf::from("C:\\install\\lib"), None)
+            .add_emit("rerun-if-changed=/source dir".to_owned())
+            .add_emit("rustc-env=MULTI_LINE=a\tb\nc".to_owned());
+
+        let serialized = result.serialize();
+        let deserialized = BuildResult::deserialize(&serialized).expect("failed to deserialize result");
+        assert_eq!(deserialized, result);
+        assert_eq!(deserialized.serialize(), serialized);
+
+        let path = std::env::temp_dir().join("__test_result_serialization").join("result");
+        result.save_to_file(&path).expect("failed to save result");
+        assert_eq!(BuildResult::load_from_file(&path).expect("failed to load result"), result);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+
+        assert!(BuildResult::deserialize("library\tstatic\tnice\n").is_err());
+        assert!(BuildResult::deserialize("# rbuild build result v1\nlibrary\tinvalid\tnice\n").is_err());
+        assert!(BuildResult::deserialize("# rbuild build result v1\nemit\tinvalid\\escape\n").is_err());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_result_merge() {
+        let mut result = BuildResult::new();
+        result.add_library("nice".to_owned(), Some(LibraryType::Shared))
+            .add_library_path(PathBuf::from("/install/lib"), Some(LinkSearchKind::Native));
+
+        let mut loaded = BuildResult::new();
+        loaded.add_library("nice".to_owned(), Some(LibraryType::Shared))
+            .add_library("srtp2".to_owned(), Some(LibraryType::Static))
+            .add_library_path(PathBuf::from("/install/lib"), Some(LinkSearchKind::Native))
+            .add_emit("rustc-cfg=srtp".to_owned());
+
+        result.merge(BuildResult::deserialize(&loaded.serialize()).unwrap());
+        assert_eq!(result.libraries().iter().map(|library| library.to_string()).collect::<Vec<_>>(), vec!["dylib=nice", "dylib=nice", "static=srtp2"]);
+        assert_eq!(result.library_paths().len(), 1);
+
+        let mut emits = BuildResult::new();
+        emits.add_emit("rustc-cfg=srtp".to_owned());
+        result.merge(emits);
+        assert_eq!(result.serialize().matches("rustc-cfg=srtp").count(), 1);
+    }
+
+    #[test]
+    fn test_skip_up_to_date_steps() {
+        let base_url = std::env::temp_dir().join("__test_step_stamps");
+        let _ = std::fs::remove_dir_all(&base_url);
+
+        let executions = Rc::new(Cell::new(0));
+        let execute_build = |option: &str, revision: &str| {
+            let mut build = Build::builder()
+                .name("stamps")
+                .source(Box::new(RevisionBuildSource{ revision: revision.to_owned() }))
+                .add_step(Box::new(CountingBuildStep{ option: option.to_owned(), executions: executions.clone() }))
+                .build_path(base_url.clone())
+                .install_prefix(base_url.join("install_root"))
+                .remove_build_dir(false)
+                .build().expect("failed to create build");
+
+            build.execute().expect("build should have succeeded").serialize()
+        };
+
+        let result = execute_build("a", "1");
+        assert_eq!(executions.get(), 1);
+
+        assert_eq!(execute_build("a", "1"), result);
+        assert_eq!(executions.get(), 1);
+
+        execute_build("b", "1");
+        assert_eq!(executions.get(), 2);
+
+        /* the tracked branch moved to another commit */
+        execute_build("b", "2");
+        assert_eq!(executions.get(), 3);
+        execute_build("b", "2");
+        assert_eq!(executions.get(), 3);
+
+        /* steps of sources without a known state must always be executed */
+        let execute_unknown_source = || {
+            let mut build = Build::builder()
+                .name("stamps")
+                .source(Box::new(DummyBuildSource{ path: PathBuf::new() }))
+                .add_step(Box::new(CountingBuildStep{ option: "a".to_owned(), executions: executions.clone() }))
+                .build_path(base_url.clone())
+                .install_prefix(base_url.join("install_root"))
+                .remove_build_dir(false)
+                .build().expect("failed to create build");
+            build.execute().expect("build should have succeeded");
+        };
+        execute_unknown_source();
+        execute_unknown_source();
+        assert_eq!(executions.get(), 5);
+
+        std::fs::remove_dir_all(&base_url).unwrap();
+    }
+}
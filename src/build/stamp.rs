@@ -0,0 +1,71 @@
+use crate::build::{Build, BuildResult};
+use std::path::PathBuf;
+
+/// A stamp marks a build step as successfully executed.
+/// It contains the build hash, the fingerprint of the set up source and the result the step produced,
+/// so the step could be skipped on the next build if nothing has changed.
+/// The step options are already part of the build hash.
+///
+/// Stamps are stored within the build path and will only survive if the build directory isn't removed.
+/// Without a source fingerprint the state of the source is unknown and the step will always be executed.
+pub(crate) struct StepStamp {
+    path: PathBuf,
+    build_hash: u64,
+    source_fingerprint: Option<u64>
+}
+
+impl StepStamp {
+    pub(crate) fn new(build: &Build, index: usize, source_fingerprint: Option<u64>) -> Self {
+        StepStamp{
+            path: build.build_path().join(".rbuild").join(format!("step_{}.stamp", index)),
+            build_hash: build.build_hash(),
+            source_fingerprint
+        }
+    }
+
+    fn header(&self, source_fingerprint: u64) -> String {
+        format!("build_hash\t{}\nsource_fingerprint\t{}\n", self.build_hash, source_fingerprint)
+    }
+
+    /// Load the result of the previous step execution.
+    /// Returns `None` if the step hasn't been executed yet or needs to be executed again.
+    pub(crate) fn load(&self) -> Option<BuildResult> {
+        let source_fingerprint = self.source_fingerprint?;
+        let data = std::fs::read_to_string(&self.path).ok()?;
+        let header = self.header(source_fingerprint);
+        if !data.starts_with(&header) {
+            return None;
+        }
+
+        match BuildResult::deserialize(&data[header.len()..]) {
+            Ok(result) => Some(result),
+            Err(error) => {
+                eprintln!("Failed to parse build stamp {:?}: {:?}", &self.path, error);
+                None
+            }
+        }
+    }
+
+    /// Store the stamp with the result of the step execution
+    pub(crate) fn store(&self, result: &BuildResult) -> std::io::Result<()> {
+        let source_fingerprint = match self.source_fingerprint {
+            Some(fingerprint) => fingerprint,
+            None => return Ok(())
+        };
+
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        std::fs::write(&self.path, self.header(source_fingerprint) + &result.serialize())
+    }
+
+    /// Remove the stamp since the step is about to be executed again
+    pub(crate) fn invalidate(&self) {
+        if self.path.exists() {
+            if let Err(error) = std::fs::remove_file(&self.path) {
+                eprintln!("Failed to remove build stamp {:?}: {:?}", &self.path, error);
+            }
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::process::Command;
+use std::path::Path;
+use std::hash::{Hash, Hasher};
+use std::cell::RefCell;
+use crate::build::BuildResult;
+
+/// The variables of the `cc` crate which configure the C/C++ toolchain
+const TOOLCHAIN_VARIABLES: [&str; 6] = ["CC", "CXX", "AR", "CFLAGS", "CXXFLAGS", "LDFLAGS"];
+
+/// Look up a tool or flags variable for the given target the same way the `cc` crate does:
+/// `<VAR>_<target>`, `<VAR>_<target_with_underscores>`, `HOST_<VAR>` or `TARGET_<VAR>` and finally `<VAR>`.
+pub(crate) fn target_variable<F>(lookup: &F, variable: &str, target: &str) -> Option<String>
+    where F: Fn(&str) -> Option<String>
+{
+    let kind = if lookup("HOST").as_deref() == Some(target) { "HOST" } else { "TARGET" };
+
+    lookup(&format!("{}_{}", variable, target))
+        .or_else(|| lookup(&format!("{}_{}", variable, target.replace('-', "_"))))
+        .or_else(|| lookup(&format!("{}_{}", kind, variable)))
+        .or_else(|| lookup(variable))
+}
+
+/// Test if a compiler command already launches the compiler through ccache or sccache
+pub(crate) fn is_launched_through_cache(compiler: &str) -> bool {
+    compiler.split_whitespace().next()
+        .and_then(|program| Path::new(program).file_stem())
+        .map(|program| program == "ccache" || program == "sccache")
+        .unwrap_or(false)
+}
+
+/// The C/C++ compilers, archiver and flags configured through the environment.
+///
+/// Within a cargo build script the variables will be resolved for the current `TARGET`
+/// with the same precedence the `cc` crate uses, so native builds use the same toolchain as the crates using `cc`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Toolchain {
+    variables: BTreeMap<String, String>,
+    /// The names of all variables which have been looked up to resolve the toolchain
+    probed_variables: BTreeSet<String>
+}
+
+impl Toolchain {
+    /// Resolve the toolchain from the environment of the current process
+    pub fn from_env() -> Self {
+        Self::resolve(|key| std::env::var(key).ok())
+    }
+
+    pub(crate) fn resolve<F>(lookup: F) -> Self
+        where F: Fn(&str) -> Option<String>
+    {
+        let target = lookup("TARGET");
+        let probed_variables = RefCell::new(BTreeSet::new());
+        let probing_lookup = |key: &str| {
+            /* HOST and TARGET are set by cargo itself */
+            if key != "HOST" {
+                probed_variables.borrow_mut().insert(key.to_owned());
+            }
+            lookup(key)
+        };
+
+        let variables = TOOLCHAIN_VARIABLES.iter()
+            .filter_map(|variable| {
+                let value = match &target {
+                    Some(target) => target_variable(&probing_lookup, variable, target),
+                    None => probing_lookup(variable)
+                };
+                value.map(|value| (variable.to_string(), value))
+            })
+            .collect();
+
+        Toolchain{ variables, probed_variables: probed_variables.into_inner() }
+    }
+
+    /// The C compiler (`CC`)
+    pub fn c_compiler(&self) -> Option<&str> {
+        self.variable("CC")
+    }
+
+    /// The C++ compiler (`CXX`)
+    pub fn cpp_compiler(&self) -> Option<&str> {
+        self.variable("CXX")
+    }
+
+    /// The archiver (`AR`)
+    pub fn archiver(&self) -> Option<&str> {
+        self.variable("AR")
+    }
+
+    /// Get the resolved value of a toolchain variable (e.g. `CFLAGS`)
+    pub fn variable(&self, name: &str) -> Option<&str> {
+        self.variables.get(name).map(String::as_str)
+    }
+
+    /// Let cargo rerun the build script if any of the variables which have been looked up for the toolchain changes
+    pub(crate) fn emit_rerun_if_env_changed(&self, result: &mut BuildResult) {
+        for variable in self.probed_variables.iter() {
+            result.add_emit(format!("rerun-if-env-changed={}", variable));
+        }
+    }
+
+    /// Launch the C and C++ compilers through the given compiler cache.
+    /// Compilers which already are launched through a compiler cache won't be wrapped again.
+    pub fn with_compiler_launcher(mut self, launcher: Option<&Path>) -> Self {
+        let launcher = match launcher {
+            Some(launcher) => launcher.to_string_lossy().into_owned(),
+            None => return self
+        };
+
+        for (variable, default_compiler) in [("CC", "cc"), ("CXX", "c++")] {
+            let compiler = self.variables.get(variable).map(String::as_str).unwrap_or(default_compiler);
+            if !is_launched_through_cache(compiler) {
+                let compiler = format!("{} {}", launcher, compiler);
+                self.variables.insert(variable.to_owned(), compiler);
+            }
+        }
+        self
+    }
+
+    /// Set the toolchain variables within the environment of the command.
+    /// The variables will be set with their plain names (e.g. `CC`), as expected by meson, cmake and autotools.
+    pub fn apply(&self, command: &mut Command) {
+        command.envs(self.variables.iter());
+    }
+}
+
+impl Hash for Toolchain {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.variables.iter().for_each(|(key, value)| {
+            key.hash(state);
+            value.hash(state);
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::build::toolchain::{target_variable, Toolchain};
+    use crate::build::BuildResult;
+    use std::collections::BTreeMap;
+    use std::process::Command;
+    use std::path::Path;
+
+    #[test]
+    fn test_target_variable() {
+        let environment = [("CC_aarch64_unknown_linux_gnu", "aarch64-cc"), ("CC", "cc"), ("TARGET_AR", "target-ar"), ("HOST_AR", "host-ar"), ("HOST", "x86_64-unknown-linux-gnu")]
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect::<BTreeMap<_, _>>();
+        let lookup = |key: &str| environment.get(key).cloned();
+
+        assert_eq!(target_variable(&lookup, "CC", "aarch64-unknown-linux-gnu").as_deref(), Some("aarch64-cc"));
+        assert_eq!(target_variable(&lookup, "CC", "armv7-unknown-linux-gnueabihf").as_deref(), Some("cc"));
+        assert_eq!(target_variable(&lookup, "AR", "armv7-unknown-linux-gnueabihf").as_deref(), Some("target-ar"));
+        assert_eq!(target_variable(&lookup, "AR", "x86_64-unknown-linux-gnu").as_deref(), Some("host-ar"));
+        assert_eq!(target_variable(&lookup, "CXX", "armv7-unknown-linux-gnueabihf"), None);
+    }
+
+    #[test]
+    fn test_toolchain() {
+        let environment = [
+            ("TARGET", "x86_64-unknown-linux-gnu"),
+            ("HOST", "x86_64-unknown-linux-gnu"),
+            ("CC_x86_64-unknown-linux-gnu", "clang"),
+            ("CC", "gcc"),
+            ("HOST_CFLAGS", "-O2"),
+            ("LDFLAGS", "-fuse-ld=lld")
+        ].iter().map(|(key, value)| (key.to_string(), value.to_string())).collect::<BTreeMap<_, _>>();
+
+        let toolchain = Toolchain::resolve(|key| environment.get(key).cloned());
+        assert_eq!(toolchain.c_compiler(), Some("clang"));
+        assert_eq!(toolchain.cpp_compiler(), None);
+        assert_eq!(toolchain.variable("CFLAGS"), Some("-O2"));
+        assert_eq!(toolchain.variable("LDFLAGS"), Some("-fuse-ld=lld"));
+
+        let mut result = BuildResult::new();
+        toolchain.emit_rerun_if_env_changed(&mut result);
+        let emits = result.serialize();
+        for variable in ["CC_x86_64-unknown-linux-gnu", "CXX_x86_64-unknown-linux-gnu", "CXX_x86_64_unknown_linux_gnu", "HOST_CXX", "CXX", "HOST_CFLAGS", "LDFLAGS"] {
+            assert!(emits.contains(&format!("emit\trerun-if-env-changed={}\n", variable)), "missing {}", variable);
+        }
+        assert!(!emits.contains("rerun-if-env-changed=HOST\n"));
+
+        /* outside of a build script only the plain variables are used */
+        let toolchain = Toolchain::resolve(|key| if key == "CC" || key == "HOST_CFLAGS" { environment.get(key).cloned() } else { None });
+        assert_eq!(toolchain.c_compiler(), Some("gcc"));
+        assert_eq!(toolchain.variable("CFLAGS"), None);
+
+        let toolchain = toolchain.with_compiler_launcher(Some(Path::new("/usr/bin/ccache")));
+        assert_eq!(toolchain.c_compiler(), Some("/usr/bin/ccache gcc"));
+        assert_eq!(toolchain.cpp_compiler(), Some("/usr/bin/ccache c++"));
+        let toolchain = toolchain.with_compiler_launcher(Some(Path::new("sccache")));
+        assert_eq!(toolchain.c_compiler(), Some("/usr/bin/ccache gcc"));
+
+        let mut command = Command::new("env");
+        toolchain.apply(&mut command);
+        let output = command.output().expect("failed to execute env");
+        assert!(String::from_utf8_lossy(&output.stdout).lines().any(|line| line == "CC=/usr/bin/ccache gcc"));
+    }
+}
@@ -10,12 +10,14 @@ pub use build::{
     BuildError,
     BuildCreateError,
 
-    BuildResult
+    BuildResult,
+    BuildResultLoadError
 };
 
 pub use util::{
     execute_build_command,
     create_temporary_path,
+    offline_mode_enabled,
 
     TemporaryPath
 };
@@ -0,0 +1,338 @@
+use crate::source::{BuildSource, SourceSetupError};
+use std::path::{PathBuf, Path};
+use std::process::Command;
+use std::io::Read;
+use crate::util::{create_temporary_path, temporary_path_location, TemporaryPath, execute_build_command};
+use std::hash::{Hash, Hasher};
+use std::collections::hash_map::DefaultHasher;
+use crate::build::BuildStepError;
+
+#[derive(Ord, PartialOrd, Eq, PartialEq, Debug, Copy, Clone)]
+pub enum ArchiveFormat {
+    Tar,
+    TarGz,
+    TarXz,
+    Zip
+}
+
+impl ArchiveFormat {
+    /// Detect the archive format by the archive file name
+    pub fn from_file_name(file_name: &str) -> Option<Self> {
+        let file_name = file_name.to_lowercase();
+        if file_name.ends_with(".tar") {
+            Some(ArchiveFormat::Tar)
+        } else if file_name.ends_with(".tar.gz") || file_name.ends_with(".tgz") {
+            Some(ArchiveFormat::TarGz)
+        } else if file_name.ends_with(".tar.xz") || file_name.ends_with(".txz") {
+            Some(ArchiveFormat::TarXz)
+        } else if file_name.ends_with(".zip") {
+            Some(ArchiveFormat::Zip)
+        } else {
+            None
+        }
+    }
+
+    fn extract_command(&self, archive: &Path, target: &Path) -> Command {
+        match self {
+            ArchiveFormat::Zip if !cfg!(windows) => {
+                let mut command = Command::new("unzip");
+                command.arg("-q")
+                    .arg(archive)
+                    .arg("-d")
+                    .arg(target);
+                command
+            },
+            _ => {
+                /* tar detects the compression by itself (bsdtar on windows handles zip files as well) */
+                let mut command = Command::new("tar");
+                command.arg("-xf")
+                    .arg(archive)
+                    .arg("-C")
+                    .arg(target);
+                command
+            }
+        }
+    }
+}
+
+/// Hash the content of a file
+pub(crate) fn hash_file_content(path: &Path, hasher: &mut dyn Hasher) -> std::io::Result<()> {
+    let mut file = std::fs::File::open(path)?;
+    let mut buffer = [0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.write(&buffer[..read]);
+    }
+    Ok(())
+}
+
+/// A source which will be unpacked from a local archive file.
+pub struct BuildSourceArchive {
+    archive: PathBuf,
+    strip_leading_component: bool,
+
+    checkout_key: Option<u64>,
+    checkout_folder: Option<PathBuf>,
+    local_folder: Option<TemporaryPath>
+}
+
+impl BuildSourceArchive {
+    pub fn builder(archive: PathBuf) -> BuildSourceArchiveBuilder {
+        BuildSourceArchiveBuilder::new(archive)
+    }
+
+    fn content_hash(&self) -> std::io::Result<u64> {
+        let mut hash = DefaultHasher::new();
+        hash_file_content(&self.archive, &mut hash)?;
+        Ok(hash.finish())
+    }
+
+    fn temporary_directory_name(&self, content_hash: u64) -> String {
+        let mut hash = DefaultHasher::new();
+        content_hash.hash(&mut hash);
+        self.strip_leading_component.hash(&mut hash);
+        self.checkout_key.hash(&mut hash);
+        let hash = hash.finish();
+        let hash = base64::encode(hash.to_be_bytes()).replace("/", "_");
+
+        let archive_name = self.archive.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_else(|| "__unknown".to_owned());
+        format!("archive_{}_{}", archive_name, hash)
+    }
+
+    /// Unpack the archive into a staging directory and move the result into the target folder at once,
+    /// so the target folder only exists if the archive has been unpacked completely.
+    fn unpack(&self, format: ArchiveFormat, target_folder: &Path, staging_folder: TemporaryPath) -> Result<(), BuildStepError> {
+        let mut command = format.extract_command(&self.archive, staging_folder.path());
+        execute_build_command(&mut command, "failed to unpack archive")?;
+
+        let content_folder = if self.strip_leading_component {
+            let entries = std::fs::read_dir(staging_folder.path())
+                .map_err(|err| BuildStepError::new_io("failed to read unpacked archive", err))?
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|err| BuildStepError::new_io("failed to read unpacked archive", err))?;
+
+            match entries.as_slice() {
+                [entry] if entry.path().is_dir() => entry.path(),
+                _ => return Err(BuildStepError::new_simple("can not strip the leading component since the archive doesn't contain exactly one top level directory"))
+            }
+        } else {
+            /* the staging folder itself becomes the target folder */
+            staging_folder.release();
+            staging_folder.path().clone()
+        };
+
+        if let Err(error) = std::fs::rename(&content_folder, target_folder) {
+            if target_folder.is_dir() {
+                /* another build unpacked the same archive in the meantime */
+                let _ = std::fs::remove_dir_all(&content_folder);
+                return Ok(());
+            }
+
+            let _ = std::fs::remove_dir_all(&content_folder);
+            return Err(BuildStepError::new_io("failed to move unpacked archive content", error));
+        }
+
+        Ok(())
+    }
+}
+
+impl BuildSource for BuildSourceArchive {
+    fn name(&self) -> &str {
+        "archive"
+    }
+
+    fn hash(&self, target: &mut Box<dyn Hasher>) {
+        match self.content_hash() {
+            Ok(hash) => hash.hash(target),
+            /* the setup will fail anyways */
+            Err(_) => self.archive.hash(target)
+        }
+        self.strip_leading_component.hash(target);
+    }
+
+    fn setup(&mut self) -> Result<(), SourceSetupError> {
+        if self.local_folder.is_some() {
+            return Err(SourceSetupError::AlreadyInitialized(BuildStepError::new_simple("the source has already been initialized")));
+        }
+
+        let format = self.archive.file_name()
+            .and_then(|name| ArchiveFormat::from_file_name(&name.to_string_lossy()))
+            .ok_or_else(|| BuildStepError::new_simple(format!("unknown archive format of {:?}", self.archive)))?;
+
+        if !self.archive.is_file() {
+            return Err(SourceSetupError::SourceDoesNotExists(BuildStepError::new_simple(format!("archive {:?} does not exist", self.archive))));
+        }
+
+        let content_hash = self.content_hash()
+            .map_err(|err| SourceSetupError::SourceIsNotReadable(BuildStepError::new_io(format!("failed to read archive {:?}", self.archive), err)))?;
+
+        let directory_name = self.temporary_directory_name(content_hash);
+        let target_folder = temporary_path_location(&directory_name, self.checkout_folder.as_ref());
+
+        if target_folder.is_dir() {
+            println!("Using already unpacked archive ({:?})", target_folder);
+        } else {
+            println!("Unpacking archive {:?}", &self.archive);
+
+            /* a staging directory of an interrupted setup will be replaced */
+            let staging_location = temporary_path_location(&format!("{}_unpack", directory_name), self.checkout_folder.as_ref());
+            let _ = std::fs::remove_dir_all(&staging_location);
+
+            let staging_folder = create_temporary_path(&format!("{}_unpack", directory_name), self.checkout_folder.as_ref())
+                .map_err(|err| SourceSetupError::TemporaryPathNotWriteable(BuildStepError::new_io("failed to create archive staging directory", err)))?;
+
+            self.unpack(format, &target_folder, staging_folder)?;
+        }
+
+        self.local_folder = Some(TemporaryPath::from_persistent(target_folder));
+        Ok(())
+    }
+
+    fn local_directory(&self) -> &PathBuf {
+        self.local_folder.as_ref().expect("expected a path")
+            .path()
+    }
+
+    fn separate_checkout(&mut self, key: u64) {
+        self.checkout_key = Some(key);
+    }
+
+    fn cleanup(&mut self) {
+        self.local_folder = None;
+    }
+}
+
+pub struct BuildSourceArchiveBuilder {
+    inner: BuildSourceArchive
+}
+
+impl BuildSourceArchiveBuilder {
+    fn new(archive: PathBuf) -> Self {
+        BuildSourceArchiveBuilder {
+            inner: BuildSourceArchive {
+                archive,
+                strip_leading_component: false,
+
+                checkout_key: None,
+                checkout_folder: None,
+                local_folder: None
+            }
+        }
+    }
+
+    /// Strip the leading directory of the archive (e.g. `libsrtp-2.3.0/`)
+    pub fn strip_leading_component(mut self, enabled: bool) -> Self {
+        self.inner.strip_leading_component = enabled;
+        self
+    }
+
+    pub fn checkout_folder(mut self, path: Option<PathBuf>) -> Self {
+        self.inner.checkout_folder = path;
+        self
+    }
+
+    pub fn build(self) -> BuildSourceArchive {
+        self.inner
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::source::{BuildSourceArchive, BuildSource, ArchiveFormat};
+    use std::process::Command;
+    use std::path::{PathBuf, Path};
+
+    fn create_archive_content(base_url: &Path) -> PathBuf {
+        let content = base_url.join("content");
+        std::fs::create_dir_all(content.join("libtest-1.0").join("src")).unwrap();
+        std::fs::write(content.join("libtest-1.0").join("meson.build"), "project('test', 'c')").unwrap();
+        std::fs::write(content.join("libtest-1.0").join("src").join("test.c"), "int test() { return 1; }").unwrap();
+        content
+    }
+
+    #[test]
+    fn test_archive_format() {
+        assert_eq!(ArchiveFormat::from_file_name("libsrtp-2.3.0.tar.gz"), Some(ArchiveFormat::TarGz));
+        assert_eq!(ArchiveFormat::from_file_name("glib.TGZ"), Some(ArchiveFormat::TarGz));
+        assert_eq!(ArchiveFormat::from_file_name("glib-2.64.2.tar.xz"), Some(ArchiveFormat::TarXz));
+        assert_eq!(ArchiveFormat::from_file_name("glib.tar"), Some(ArchiveFormat::Tar));
+        assert_eq!(ArchiveFormat::from_file_name("glib.zip"), Some(ArchiveFormat::Zip));
+        assert_eq!(ArchiveFormat::from_file_name("glib.rar"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_tar_archive() {
+        let base_url = std::env::temp_dir().join("__test_archive_tar");
+        let _ = std::fs::remove_dir_all(&base_url);
+        let content = create_archive_content(&base_url);
+
+        let archive = base_url.join("libtest-1.0.tar.gz");
+        let status = Command::new("tar").arg("-czf").arg(&archive).arg("-C").arg(&content).arg("libtest-1.0").status().unwrap();
+        assert!(status.success());
+
+        let mut source = BuildSourceArchive::builder(archive.clone())
+            .checkout_folder(Some(base_url.clone()))
+            .strip_leading_component(true)
+            .build();
+        source.setup().expect("failed to setup archive source");
+        assert!(source.local_directory().join("meson.build").is_file());
+        assert!(source.local_directory().join("src").join("test.c").is_file());
+
+        /* a second setup should reuse the already unpacked archive */
+        source.cleanup();
+        source.setup().expect("failed to setup archive source again");
+        assert!(source.local_directory().join("meson.build").is_file());
+
+        let mut source = BuildSourceArchive::builder(archive.clone())
+            .checkout_folder(Some(base_url.clone()))
+            .build();
+        source.setup().expect("failed to setup archive source");
+        assert!(source.local_directory().join("libtest-1.0").join("meson.build").is_file());
+
+        /* a failed unpack must neither leave a target folder behind nor prevent a retry */
+        let broken_archive = base_url.join("broken").join("libtest-1.0.tar.gz");
+        std::fs::create_dir_all(broken_archive.parent().unwrap()).unwrap();
+        std::fs::write(&broken_archive, "not an archive").unwrap();
+
+        let mut source = BuildSourceArchive::builder(broken_archive.clone())
+            .checkout_folder(Some(base_url.join("broken")))
+            .build();
+        source.setup().expect_err("unpacking an invalid archive should fail");
+        let unpacked = std::fs::read_dir(base_url.join("broken")).unwrap()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with("archive_"))
+            .count();
+        assert_eq!(unpacked, 0);
+
+        std::fs::copy(&archive, &broken_archive).unwrap();
+        source.setup().expect("failed to setup the archive source after fixing the archive");
+        assert!(source.local_directory().join("libtest-1.0").join("meson.build").is_file());
+
+        std::fs::remove_dir_all(&base_url).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_zip_archive() {
+        let base_url = std::env::temp_dir().join("__test_archive_zip");
+        let _ = std::fs::remove_dir_all(&base_url);
+        let content = create_archive_content(&base_url);
+
+        let archive = base_url.join("libtest-1.0.zip");
+        let status = Command::new("zip").arg("-qr").arg(&archive).arg("libtest-1.0").current_dir(&content).status().unwrap();
+        assert!(status.success());
+
+        let mut source = BuildSourceArchive::builder(archive)
+            .checkout_folder(Some(base_url.clone()))
+            .strip_leading_component(true)
+            .build();
+        source.setup().expect("failed to setup archive source");
+        assert!(source.local_directory().join("src").join("test.c").is_file());
+
+        std::fs::remove_dir_all(&base_url).unwrap();
+    }
+}
@@ -1,13 +1,16 @@
-use crate::source::{BuildSource};
-use std::path::{PathBuf};
+use crate::source::{BuildSource, SourceSetupError};
+use std::path::{PathBuf, Path};
 use std::process::Command;
 use std::io::ErrorKind;
 use lazy_static::lazy_static;
 use std::ops::Deref;
-use crate::util::{create_temporary_path, TemporaryPath, execute_build_command};
+use crate::util::{create_temporary_path, TemporaryPath, execute_build_command, offline_mode_enabled};
 use std::hash::{Hash, Hasher};
 use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
 use crate::build::BuildStepError;
+use crate::resolve_env_var;
+use std::env;
 
 #[derive(Ord, PartialOrd, Eq, PartialEq, Debug)]
 enum GitBinaryStatus {
@@ -52,12 +55,33 @@ lazy_static! {
 
 pub struct BuildSourceGit {
     repository_url: String,
-    /* TODO: Branch? */
     revision: Option<String>,
+    branch: Option<String>,
+    tag: Option<String>,
+    resolved_revision: Option<String>,
 
     checkout_submodule: bool,
+    submodule_paths: Vec<String>,
     skip_revision_checkout: bool,
 
+    clone_depth: Option<u32>,
+    single_branch: bool,
+    blobless: bool,
+    fetch_revision_only: bool,
+
+    mirror_folder: Option<PathBuf>,
+    mirror: Option<PathBuf>,
+    offline: Option<bool>,
+
+    require_pinned_revision: bool,
+    signature_keyring: Option<PathBuf>,
+
+    clean_worktree: bool,
+    refuse_local_modifications: bool,
+
+    git_config: BTreeMap<String, String>,
+
+    checkout_key: Option<u64>,
     checkout_folder: Option<PathBuf>,
     local_folder: Option<TemporaryPath>
 }
@@ -71,12 +95,482 @@ impl BuildSourceGit {
         let mut hash = DefaultHasher::new();
         self.repository_url.hash(&mut hash);
         self.revision.as_ref().map(|e| e.hash(&mut hash));
+        /* the refspecs and the history of a checkout depend on how it has been cloned */
+        self.branch.hash(&mut hash);
+        self.tag.hash(&mut hash);
+        self.clone_depth.hash(&mut hash);
+        self.single_branch.hash(&mut hash);
+        self.blobless.hash(&mut hash);
+        self.checkout_key.hash(&mut hash);
         let hash = hash.finish();
         let hash = base64::encode(hash.to_be_bytes()).replace("/", "_");
 
         let project_name = self.repository_url.split("/").last().unwrap_or("__unknown");
         format!("git_{}_{}", project_name, hash).to_owned()
     }
+
+    /// Get the directory containing the shared bare mirrors.
+    /// The builder option has precedence over the `rbuild_git_mirror_dir` environment variable.
+    fn mirror_folder(&self) -> Option<PathBuf> {
+        self.mirror_folder.clone()
+            .or_else(|| resolve_env_var!("git_mirror_dir").map(PathBuf::from))
+            .filter(|folder| !folder.as_os_str().is_empty())
+    }
+
+    fn mirror_path(&self, mirror_folder: &Path) -> PathBuf {
+        let mut hash = DefaultHasher::new();
+        self.repository_url.hash(&mut hash);
+        let hash = base64::encode(hash.finish().to_be_bytes()).replace("/", "_");
+
+        let project_name = self.repository_url.trim_end_matches('/').rsplit('/').next().unwrap_or("__unknown");
+        let project_name = project_name.strip_suffix(".git").unwrap_or(project_name);
+        mirror_folder.join(format!("{}_{}.git", project_name, hash))
+    }
+
+    /// Create a git command with the configured git options.
+    /// git will report its messages untranslated, so failures can be classified by them.
+    fn git_command(&self) -> Command {
+        let mut command = Command::new("git");
+        command.env("LC_ALL", "C");
+        for (key, value) in self.git_config.iter() {
+            command.arg("-c").arg(format!("{}={}", key, value));
+        }
+        command
+    }
+
+    fn offline(&self) -> bool {
+        self.offline.unwrap_or_else(offline_mode_enabled)
+    }
+
+    /// The location the checkout will be fetched from: the local mirror if enabled, else the origin remote.
+    /// The origin remote always points to the repository itself, so relative submodule urls resolve against it.
+    fn fetch_location(&self) -> String {
+        match &self.mirror {
+            Some(mirror) => mirror.to_string_lossy().into_owned(),
+            None => "origin".to_owned()
+        }
+    }
+
+    /// Add the location and the refspec for fetching all branches and tags to the fetch command
+    fn add_fetch_location(&self, command: &mut Command) {
+        command.arg(self.fetch_location());
+        if self.mirror.is_some() {
+            /* the mirror isn't a configured remote, so its branches must be mapped onto the origin branches explicitly */
+            command.arg("+refs/heads/*:refs/remotes/origin/*");
+        }
+    }
+
+    /// Create or update the shared bare mirror of the repository
+    fn update_mirror(&self, mirror: &Path) -> Result<(), BuildStepError> {
+        if mirror.join("HEAD").exists() {
+            if let Some(revision) = self.pinned_revision() {
+                if self.resolve_commit(mirror, revision).is_some() {
+                    /* the mirror already contains the pinned revision */
+                    return Ok(());
+                }
+            }
+
+            println!("Updating git mirror ({:?})", mirror);
+            let mut command = self.git_command();
+            command.arg("fetch")
+                   .arg("--prune")
+                   .arg("origin")
+                   .current_dir(mirror);
+            execute_build_command(&mut command, "git mirror fetch failed")?;
+            return Ok(());
+        }
+
+        println!("Creating git mirror ({:?})", mirror);
+        if let Some(parent) = mirror.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|err| BuildStepError::new_io("failed to create git mirror directory", err))?;
+        }
+
+        /* other builds may use the mirror concurrently, so only move complete mirrors into place */
+        let mut staging_path = mirror.as_os_str().to_owned();
+        staging_path.push(format!(".tmp{}", std::process::id()));
+        let staging_path = PathBuf::from(staging_path);
+        let _ = std::fs::remove_dir_all(&staging_path);
+
+        let mut command = self.git_command();
+        command.arg("clone")
+               .arg("--mirror")
+               .arg(&self.repository_url)
+               .arg(&staging_path);
+        execute_build_command(&mut command, "git mirror clone failed")?;
+
+        if let Err(error) = std::fs::rename(&staging_path, mirror) {
+            let _ = std::fs::remove_dir_all(&staging_path);
+            if !mirror.join("HEAD").exists() {
+                return Err(BuildStepError::new_io("failed to move git mirror into place", error));
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the commit SHA which has been checked out by the last setup
+    pub fn resolved_revision(&self) -> Option<&str> {
+        self.resolved_revision.as_deref()
+    }
+
+    /// Get the revision which should be checked out.
+    /// An explicit revision has precedence over a tag which has precedence over a branch.
+    fn target_revision(&self) -> String {
+        if let Some(revision) = &self.revision {
+            revision.clone()
+        } else if let Some(tag) = &self.tag {
+            format!("refs/tags/{}", tag)
+        } else if let Some(branch) = &self.branch {
+            /* the local branch will not be updated by a fetch, so use the remote one */
+            format!("origin/{}", branch)
+        } else {
+            "origin/HEAD".to_owned()
+        }
+    }
+
+    /// Test whatever only the pinned revision should be fetched
+    fn pinned_revision(&self) -> Option<&String> {
+        if self.fetch_revision_only { self.revision.as_ref() } else { None }
+    }
+
+    fn clone_repository(&self, target_folder: &Path) -> Result<(), BuildStepError> {
+        if self.pinned_revision().is_some() {
+            /* we can't clone a single commit, so initialize an empty repository and fetch it afterwards */
+            let mut command = self.git_command();
+            command.arg("init")
+                   .arg(target_folder);
+            execute_build_command(&mut command, "git init failed")?;
+
+            let mut command = self.git_command();
+            command.arg("remote")
+                   .arg("add")
+                   .arg("origin")
+                   .arg(&self.repository_url)
+                   .current_dir(target_folder);
+            execute_build_command(&mut command, "git remote add failed")?;
+
+            return self.fetch_repository(target_folder);
+        }
+
+        let mut command = self.git_command();
+        command.arg("clone");
+
+        /* the history and contents of a local mirror are available anyways */
+        let local_mirror = self.mirror.is_some();
+        if let Some(depth) = self.clone_depth.filter(|_| !local_mirror) {
+            command.arg(format!("--depth={}", depth));
+            if !self.single_branch {
+                /* --depth implies --single-branch */
+                command.arg("--no-single-branch");
+            }
+        } else if self.single_branch {
+            command.arg("--single-branch");
+        }
+
+        if self.single_branch {
+            if let Some(reference) = self.tag.as_ref().or(self.branch.as_ref()) {
+                command.arg("--branch").arg(reference);
+            }
+        }
+
+        if self.blobless && !local_mirror {
+            command.arg("--filter=blob:none");
+        }
+
+        match &self.mirror {
+            Some(mirror) => command.arg(mirror),
+            None => command.arg(&self.repository_url)
+        };
+        command.arg(target_folder);
+        execute_build_command(&mut command, "git clone failed")?;
+
+        if self.mirror.is_some() {
+            self.set_origin_url(target_folder)?;
+        }
+        Ok(())
+    }
+
+    fn set_origin_url(&self, target_folder: &Path) -> Result<(), BuildStepError> {
+        let mut command = self.git_command();
+        command.arg("remote")
+               .arg("set-url")
+               .arg("origin")
+               .arg(&self.repository_url)
+               .current_dir(target_folder);
+        execute_build_command(&mut command, "git remote set-url failed")?;
+        Ok(())
+    }
+
+    fn fetch_repository(&self, target_folder: &Path) -> Result<(), BuildStepError> {
+        /* older checkouts used the mirror as origin */
+        self.set_origin_url(target_folder)?;
+
+        if let Some(revision) = self.pinned_revision() {
+            if self.resolve_commit(target_folder, revision).is_some() {
+                /* we already got the revision */
+                return Ok(());
+            }
+
+            let mut command = self.git_command();
+            command.arg("fetch");
+            if let Some(depth) = self.clone_depth {
+                command.arg(format!("--depth={}", depth));
+            }
+            if self.blobless {
+                command.arg("--filter=blob:none");
+            }
+            command.arg(self.fetch_location())
+                   .arg(revision)
+                   .current_dir(target_folder);
+
+            match execute_build_command(&mut command, "git fetch failed") {
+                Ok(_) => return Ok(()),
+                Err(error) => {
+                    if error.stderr().contains("not a git repository") {
+                        return Err(error);
+                    }
+
+                    /* not all servers allow fetching arbitrary commits */
+                    println!("Failed to fetch revision {} only. Fetching the whole repository.", revision);
+                }
+            }
+        }
+
+        let mut command = self.git_command();
+        command.arg("fetch");
+        if let Some(depth) = self.clone_depth.filter(|_| self.pinned_revision().is_none()) {
+            command.arg(format!("--depth={}", depth));
+        } else if target_folder.join(".git").join("shallow").exists() {
+            command.arg("--unshallow");
+        }
+        if self.tag.is_some() || self.pinned_revision().is_some() {
+            /* tags which have been moved will be rejected instead of being updated */
+            command.arg("--tags");
+        }
+        self.add_fetch_location(&mut command);
+        command.current_dir(target_folder);
+
+        execute_build_command(&mut command, "git fetch failed")?;
+        Ok(())
+    }
+
+    /// Get the pinned commit SHA if the pinned mode is enabled
+    fn required_commit(&self) -> Result<Option<String>, SourceSetupError> {
+        if !self.require_pinned_revision {
+            return Ok(None);
+        }
+
+        match &self.revision {
+            Some(revision) if revision.len() == 40 && revision.chars().all(|c| c.is_ascii_hexdigit()) => Ok(Some(revision.to_lowercase())),
+            revision => Err(SourceSetupError::VerificationFailed(BuildStepError::new_simple(format!("a full 40 character commit SHA is required as revision, but got {:?}", revision))))
+        }
+    }
+
+    /// Verify the signature of the checked out tag or commit against the configured keyring
+    fn verify_signature(&self, folder: &Path, commit: &str) -> Result<(), SourceSetupError> {
+        let keyring = match &self.signature_keyring {
+            Some(keyring) => keyring,
+            None => return Ok(())
+        };
+
+        let mut command = self.git_command();
+        if let Some(tag) = &self.tag {
+            println!("Verifying signature of tag {}", tag);
+            command.arg("verify-tag").arg(tag);
+        } else {
+            println!("Verifying signature of commit {}", commit);
+            command.arg("verify-commit").arg(commit);
+        }
+        command.env("GNUPGHOME", keyring)
+               .current_dir(folder);
+
+        execute_build_command(&mut command, "git signature verification failed")
+            .map_err(SourceSetupError::VerificationFailed)?;
+        Ok(())
+    }
+
+    /// Fail if the checkout contains modifications which would be lost by the setup
+    fn check_local_modifications(&self, folder: &Path) -> Result<(), SourceSetupError> {
+        let mut command = self.git_command();
+        command.arg("status")
+               .arg("--porcelain");
+
+        if self.clean_worktree {
+            command.arg("--ignore-submodules=none");
+        } else {
+            /* untracked files will only be removed when cleaning the worktree */
+            command.arg("--untracked-files=no")
+                   .arg("--ignore-submodules=untracked");
+        }
+
+        command.current_dir(folder);
+        let (stdout, _) = execute_build_command(&mut command, "git status failed")?;
+        if stdout.trim().is_empty() {
+            return Ok(());
+        }
+
+        /* modifications which have been made by the setup itself (e.g. applied patches) will be overwritten anyways */
+        if let Ok(accepted_tree) = std::fs::read_to_string(Self::accepted_tree_path(folder)) {
+            if self.worktree_tree(folder)? == accepted_tree.trim() {
+                return Ok(());
+            }
+        }
+
+        Err(SourceSetupError::LocalModifications(BuildStepError::new(
+            format!("the checkout {:?} contains local modifications", folder),
+            stdout,
+            String::new()
+        )))
+    }
+
+    /// Fail if one of the selected submodules (or their nested submodules) would have to be cloned.
+    /// A submodule is available locally if it has been checked out or its git directory still exists.
+    fn check_offline_submodules(&self, folder: &Path, selected_paths: &[String]) -> Result<(), SourceSetupError> {
+        if !folder.join(".gitmodules").exists() {
+            return Ok(());
+        }
+
+        let mut command = self.git_command();
+        command.arg("config")
+               .arg("-z")
+               .arg("--file").arg(".gitmodules")
+               .arg("--get-regexp").arg(r"^submodule\..*\.path$")
+               .current_dir(folder);
+        let (stdout, _) = match execute_build_command(&mut command, "git config failed") {
+            Ok(output) => output,
+            Err(error) if error.exit_code() == Some(1) => return Ok(()), /* no submodules */
+            Err(error) => return Err(error.into())
+        };
+
+        for entry in stdout.split('\0') {
+            let (name, path) = match entry.split_once('\n') {
+                Some((key, path)) => match key.strip_prefix("submodule.").and_then(|key| key.strip_suffix(".path")) {
+                    Some(name) => (name, path),
+                    None => continue
+                },
+                None => continue
+            };
+
+            if !selected_paths.is_empty() && !selected_paths.iter().any(|selected| path == selected || path.starts_with(&format!("{}/", selected.trim_end_matches('/')))) {
+                continue;
+            }
+
+            if folder.join(path).join(".git").exists() {
+                self.check_offline_submodules(&folder.join(path), &[])?;
+                continue;
+            }
+
+            let mut command = self.git_command();
+            command.arg("rev-parse")
+                   .arg("--git-path")
+                   .arg(format!("modules/{}", name))
+                   .current_dir(folder);
+            let (stdout, _) = execute_build_command(&mut command, "git rev-parse failed")?;
+            if !folder.join(stdout.trim()).join("HEAD").exists() {
+                return Err(SourceSetupError::SourceDoesNotExists(BuildStepError::new_simple(format!(
+                    "offline mode: git submodule {} of {} is not available locally", path, &self.repository_url
+                ))));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn accepted_tree_path(folder: &Path) -> PathBuf {
+        folder.join(".git").join("rbuild_accepted_tree")
+    }
+
+    /// Write the current state of the worktree as tree object and return its id.
+    /// Untracked files are only included if the worktree will be cleaned.
+    fn worktree_tree(&self, folder: &Path) -> Result<String, BuildStepError> {
+        /* use a separate index, so the index of the checkout stays untouched */
+        let index = folder.join(".git").join("rbuild_index");
+        let _ = std::fs::remove_file(&index);
+
+        let commands: [&[&str]; 3] = [
+            &["read-tree", "HEAD"],
+            &["add", if self.clean_worktree { "--all" } else { "--update" }],
+            &["write-tree"]
+        ];
+
+        let mut tree = String::new();
+        for args in commands.iter() {
+            let mut command = self.git_command();
+            command.args(args.iter())
+                   .env("GIT_INDEX_FILE", &index)
+                   .current_dir(folder);
+            let result = execute_build_command(&mut command, "failed to snapshot the git worktree");
+            if result.is_err() {
+                let _ = std::fs::remove_file(&index);
+            }
+            tree = result?.0.trim().to_owned();
+        }
+
+        let _ = std::fs::remove_file(&index);
+        Ok(tree)
+    }
+
+    /// The file which records the commit a tag pointed to when it has been checked out for the first time.
+    /// The record is kept next to the checkout, so neither a forced tag update nor a fresh clone can change it.
+    fn tag_record_path(target_folder: &Path) -> PathBuf {
+        let mut path = target_folder.as_os_str().to_owned();
+        path.push(".tags");
+        PathBuf::from(path)
+    }
+
+    /// Fail if the tag points to another commit than it did when it has been checked out for the first time.
+    /// Tags which haven't been seen yet will be recorded.
+    fn verify_tag_record(&self, target_folder: &Path, tag: &str) -> Result<(), SourceSetupError> {
+        let record_path = Self::tag_record_path(target_folder);
+        let tag_commit = match self.resolve_commit(target_folder, &format!("refs/tags/{}", tag)) {
+            Some(commit) => commit,
+            /* a missing tag will be reported when resolving the revision */
+            None => return Ok(())
+        };
+
+        let records = match std::fs::read_to_string(&record_path) {
+            Ok(records) => records,
+            Err(error) if error.kind() == ErrorKind::NotFound => String::new(),
+            Err(error) => return Err(BuildStepError::new_io("failed to read the git tag record", error).into())
+        };
+
+        let recorded_commit = records.lines()
+            .filter_map(|line| line.split_once('\t'))
+            .find(|(name, _)| *name == tag)
+            .map(|(_, commit)| commit);
+
+        match recorded_commit {
+            Some(commit) if commit == tag_commit => Ok(()),
+            Some(commit) => Err(SourceSetupError::VerificationFailed(BuildStepError::new_simple(format!(
+                "tag {} has been moved from {} to {}. Remove {:?} if this is intended.",
+                tag, commit, tag_commit, record_path
+            )))),
+            None => {
+                let records = format!("{}{}\t{}\n", records, tag, tag_commit);
+                std::fs::write(&record_path, records)
+                    .map_err(|error| BuildStepError::new_io("failed to write the git tag record", error))?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Resolve a revision to its full commit SHA
+    fn resolve_commit(&self, folder: &Path, revision: &str) -> Option<String> {
+        let mut command = self.git_command();
+        command.arg("rev-parse")
+               .arg("--verify")
+               .arg("--quiet")
+               .arg(format!("{}^{{commit}}", revision))
+               .current_dir(folder);
+
+        let output = command.output().ok()?;
+        if !output.status.success() {
+            return None;
+        }
+
+        let commit = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+        if commit.is_empty() { None } else { Some(commit) }
+    }
 }
 
 impl BuildSource for BuildSourceGit {
@@ -87,37 +581,66 @@ impl BuildSource for BuildSourceGit {
     fn hash(&self, target: &mut Box<dyn Hasher>) {
         self.repository_url.hash(target);
         self.revision.hash(target);
+        self.branch.hash(target);
+        self.tag.hash(target);
+        self.checkout_submodule.hash(target);
+        self.submodule_paths.hash(target);
     }
 
-    fn setup(&mut self) -> Result<(), BuildStepError> {
+    fn setup(&mut self) -> Result<(), SourceSetupError> {
         if self.local_folder.is_some() {
-            return Err(BuildStepError::new_simple("the source has already been initialized"));
+            return Err(SourceSetupError::AlreadyInitialized(BuildStepError::new_simple("the source has already been initialized")));
         }
 
         if !matches!(GIT_STATUS.deref(), GitBinaryStatus::Ok(_)) {
-            return Err(BuildStepError::new_simple(format!("git error: {:?}", GIT_STATUS.deref())));
+            return Err(BuildStepError::new_simple(format!("git error: {:?}", GIT_STATUS.deref())).into());
         }
 
+        let required_commit = self.required_commit()?;
+
         let target_folder = match create_temporary_path(&self.temporary_directory_name(), self.checkout_folder.as_ref()) {
             Ok(folder) => {
                 folder.release(); /* FIXME! */
                 self.local_folder = Some(folder.clone());
                 folder
             },
-            Err(err) => return Err(BuildStepError::new_simple(format!("failed to create git checkout directory: {:?}", err)))
+            Err(err) => return Err(SourceSetupError::TemporaryPathNotWriteable(BuildStepError::new_io("failed to create git checkout directory", err)))
         };
 
+        /* in offline mode we're only allowed to fetch from an already existing mirror */
+        let offline = self.offline();
+        self.mirror = self.mirror_folder()
+            .map(|folder| self.mirror_path(&folder))
+            .filter(|mirror| !offline || mirror.join("HEAD").exists());
+        if let Some(mirror) = &self.mirror {
+            if offline {
+                println!("Offline mode: using git mirror {:?} without updating it", mirror);
+            } else {
+                self.update_mirror(mirror)?;
+            }
+        }
+        let fetch_allowed = !offline || self.mirror.is_some();
+
+        if self.refuse_local_modifications && target_folder.join(".git").exists() {
+            self.check_local_modifications(&target_folder)?;
+        }
+
         let mut repository_exists = false;
-        if target_folder.join(".git").exists() {
+        if target_folder.join(".git").exists() && !fetch_allowed {
+            println!("Offline mode: using existing repository ({:?}) without fetching", target_folder);
+            repository_exists = true;
+        } else if target_folder.join(".git").exists() {
             println!("Updating existing repository ({:?})", target_folder);
 
-            let mut command = Command::new("git");
-            command.arg("fetch")
-                   .current_dir(target_folder.deref());
-
-            if let Err(error) = execute_build_command(&mut command, "git fetch failed") {
-                if error.stderr().find("not a git repository").is_none() {
-                    return Err(error);
+            if let Err(error) = self.fetch_repository(&target_folder) {
+                if error.stderr().contains("would clobber existing tag") {
+                    return Err(SourceSetupError::VerificationFailed(BuildStepError::new(
+                        format!("a tag of {} has been moved. Remove {:?} and {:?} if this is intended.", &self.repository_url, target_folder.deref(), Self::tag_record_path(&target_folder)),
+                        error.stdout().to_owned(),
+                        error.stderr().to_owned()
+                    )));
+                } else if error.stderr().find("not a git repository").is_none() {
+                    return Err(error.into());
                 } else {
                     std::fs::remove_dir_all(target_folder.deref())
                         .map_err(|err| BuildStepError::new_io("failed to remove old temporary checkout directory", err))?;
@@ -131,30 +654,138 @@ impl BuildSource for BuildSourceGit {
         }
 
         if !repository_exists {
+            if !fetch_allowed {
+                return Err(SourceSetupError::SourceDoesNotExists(BuildStepError::new_simple(format!("offline mode: neither a checkout nor a mirror of {} is available locally", &self.repository_url))));
+            }
+
             println!("Cloning git repository");
+            self.clone_repository(&target_folder)?;
+        }
 
-            let mut command = Command::new("git");
-            command.arg("clone")
-                   .arg(&self.repository_url)
-                   .arg(target_folder.deref());
+        if let (Some(tag), Some(required_commit)) = (&self.tag, &required_commit) {
+            let tag_commit = self.resolve_commit(&target_folder, &format!("refs/tags/{}", tag));
+            if tag_commit.as_ref() != Some(required_commit) {
+                return Err(SourceSetupError::VerificationFailed(BuildStepError::new_simple(format!(
+                    "tag {} points to {} instead of the pinned revision {}",
+                    tag, tag_commit.as_deref().unwrap_or("nothing"), required_commit
+                ))));
+            }
+        }
 
-            execute_build_command(&mut command, "git clone failed")?;
+        if let Some(tag) = &self.tag {
+            self.verify_tag_record(&target_folder, tag)?;
         }
 
         if !self.skip_revision_checkout {
-            let revision = self.revision.clone().unwrap_or("HEAD".to_owned());
-            println!("Checking out revision {}", &revision);
+            let mut revision = self.target_revision();
+            if self.revision.is_none() && self.tag.is_none() && self.branch.is_none() && self.resolve_commit(&target_folder, &revision).is_none() {
+                /* the remote doesn't advertise its HEAD */
+                revision = "HEAD".to_owned();
+            }
+
+            let mut commit = self.resolve_commit(&target_folder, &revision);
+            if commit.is_none() && fetch_allowed && target_folder.join(".git").join("shallow").exists() {
+                /* the revision might not be part of the shallow history */
+                println!("Revision {} not found within the shallow history. Fetching the full history.", &revision);
+
+                let mut command = self.git_command();
+                command.arg("fetch")
+                       .arg("--unshallow")
+                       .arg("--tags");
+                self.add_fetch_location(&mut command);
+                command.current_dir(target_folder.deref());
+                execute_build_command(&mut command, "git fetch failed")?;
+
+                commit = self.resolve_commit(&target_folder, &revision);
+            }
 
+            let commit = match commit {
+                Some(commit) => commit,
+                None if offline => return Err(SourceSetupError::RevisionNotFound(BuildStepError::new_simple(format!("offline mode: git revision {} of {} is not available locally", &revision, &self.repository_url)))),
+                None => return Err(SourceSetupError::RevisionNotFound(BuildStepError::new_simple(format!("failed to resolve git revision {}", &revision))))
+            };
+            /* verify the signature before the content gets checked out */
+            self.verify_signature(&target_folder, &commit)?;
+            println!("Checking out revision {} ({})", &revision, &commit);
 
-            let mut command = Command::new("git");
+            let mut command = self.git_command();
             command.arg("reset")
                    .arg("--hard")
-                   .arg(&revision)
+                   .arg(&commit)
                    .current_dir(target_folder.deref());
 
             execute_build_command(&mut command, "git revision checkout failed")?;
         }
 
+        self.resolved_revision = self.resolve_commit(&target_folder, "HEAD");
+
+        if let Some(required_commit) = &required_commit {
+            if self.resolved_revision.as_ref() != Some(required_commit) {
+                return Err(SourceSetupError::VerificationFailed(BuildStepError::new_simple(format!(
+                    "checked out revision {} doesn't match the pinned revision {}",
+                    self.resolved_revision.as_deref().unwrap_or("nothing"), required_commit
+                ))));
+            }
+        }
+
+        if self.skip_revision_checkout {
+            self.verify_signature(&target_folder, self.resolved_revision.as_deref().unwrap_or("HEAD"))?;
+        }
+
+        if self.checkout_submodule {
+            /* the submodule revisions depend on the checked out revision, so update them afterwards */
+            println!("Updating git submodules");
+
+            if offline {
+                self.check_offline_submodules(&target_folder, &self.submodule_paths)?;
+            }
+
+            let mut command = self.git_command();
+            command.arg("submodule")
+                   .arg("update")
+                   .arg("--init")
+                   .arg("--recursive");
+
+            if offline {
+                command.arg("--no-fetch");
+            }
+
+            if !self.submodule_paths.is_empty() {
+                command.arg("--").args(&self.submodule_paths);
+            }
+
+            command.current_dir(target_folder.deref());
+            if let Err(error) = execute_build_command(&mut command, "git submodule update failed") {
+                if offline {
+                    return Err(SourceSetupError::RevisionNotFound(BuildStepError::new(
+                        format!("offline mode: the git submodule revisions of {} are not available locally", &self.repository_url),
+                        error.stdout().to_owned(),
+                        error.stderr().to_owned()
+                    )));
+                }
+                return Err(error.into());
+            }
+        }
+
+        if self.clean_worktree {
+            println!("Removing untracked files");
+
+            let mut command = self.git_command();
+            command.arg("clean")
+                   .arg("-fdx")
+                   .current_dir(target_folder.deref());
+            execute_build_command(&mut command, "git clean failed")?;
+
+            /* the submodules have been checked out at their recorded revision, so resetting them is safe */
+            let mut command = self.git_command();
+            command.arg("submodule")
+                   .arg("foreach")
+                   .arg("--recursive")
+                   .arg("git reset --hard && git clean -fdx")
+                   .current_dir(target_folder.deref());
+            execute_build_command(&mut command, "git submodule clean failed")?;
+        }
+
         Ok(())
     }
 
@@ -163,6 +794,41 @@ impl BuildSource for BuildSourceGit {
             .path()
     }
 
+    fn resolved_revision(&self) -> Option<&str> {
+        self.resolved_revision.as_deref()
+    }
+
+    fn fingerprint(&self, target: &mut Box<dyn Hasher>) -> Result<bool, SourceSetupError> {
+        if self.skip_revision_checkout {
+            /* the working copy may contain changes which aren't part of the checked out revision */
+            return Ok(false);
+        }
+
+        match &self.resolved_revision {
+            Some(revision) => {
+                revision.hash(target);
+                Ok(true)
+            },
+            None => Ok(false)
+        }
+    }
+
+    fn accept_local_modifications(&mut self) -> Result<(), SourceSetupError> {
+        if !self.refuse_local_modifications {
+            return Ok(());
+        }
+
+        let folder = self.local_directory().clone();
+        let tree = self.worktree_tree(&folder)?;
+        std::fs::write(Self::accepted_tree_path(&folder), tree)
+            .map_err(|err| BuildStepError::new_io("failed to record the accepted git worktree", err))?;
+        Ok(())
+    }
+
+    fn separate_checkout(&mut self, key: u64) {
+        self.checkout_key = Some(key);
+    }
+
     fn cleanup(&mut self) {
         /* FIXME: Remove this? */
         self.local_folder.as_mut().map(|e| e.release());
@@ -181,20 +847,50 @@ impl BuildSourceGitBuilder {
                 repository_url,
 
                 checkout_submodule: false,
+                submodule_paths: Vec::new(),
                 skip_revision_checkout: false,
 
+                clone_depth: None,
+                single_branch: false,
+                blobless: false,
+                fetch_revision_only: false,
+
+                mirror_folder: None,
+                mirror: None,
+                offline: None,
+
+                require_pinned_revision: false,
+                signature_keyring: None,
+
+                clean_worktree: false,
+                refuse_local_modifications: false,
+
+                git_config: BTreeMap::new(),
+
+                checkout_key: None,
                 checkout_folder: None,
                 local_folder: None,
-                revision: None
+                revision: None,
+                branch: None,
+                tag: None,
+                resolved_revision: None
             }
         }
     }
 
+    /// Initialize and update all submodules (recursively) after the revision has been checked out
     pub fn checkout_submodule(mut self, enabled: bool) -> Self {
         self.inner.checkout_submodule = enabled;
         self
     }
 
+    /// Only initialize the submodules at the given paths.
+    /// If empty all submodules will be initialized.
+    pub fn submodule_paths(mut self, paths: Vec<String>) -> Self {
+        self.inner.submodule_paths = paths;
+        self
+    }
+
     pub fn checkout_folder(mut self, path: Option<PathBuf>) -> Self {
         self.inner.checkout_folder = path;
         self
@@ -205,11 +901,99 @@ impl BuildSourceGitBuilder {
         self
     }
 
+    /// Track the given remote branch.
+    /// Every setup will check out the latest fetched commit of `origin/<branch>`.
+    pub fn branch(mut self, branch: Option<String>) -> Self {
+        self.inner.branch = branch;
+        self
+    }
+
+    /// Check out the given tag
+    pub fn tag(mut self, tag: Option<String>) -> Self {
+        self.inner.tag = tag;
+        self
+    }
+
     pub fn skip_revision_checkout(mut self, enabled: bool) -> Self {
         self.inner.skip_revision_checkout = enabled;
         self
     }
 
+    /// Limit the fetched history to the given amount of commits (`--depth`)
+    pub fn clone_depth(mut self, depth: Option<u32>) -> Self {
+        self.inner.clone_depth = depth;
+        self
+    }
+
+    /// Only clone the history of the selected branch or tag (`--single-branch`)
+    pub fn single_branch(mut self, enabled: bool) -> Self {
+        self.inner.single_branch = enabled;
+        self
+    }
+
+    /// Create a partial clone without any file contents which aren't required for the checkout (`--filter=blob:none`)
+    pub fn blobless(mut self, enabled: bool) -> Self {
+        self.inner.blobless = enabled;
+        self
+    }
+
+    /// Only fetch the pinned revision (which must be a commit SHA) instead of the whole repository.
+    /// If the server refuses to serve the commit, the whole repository will be fetched.
+    pub fn fetch_revision_only(mut self, enabled: bool) -> Self {
+        self.inner.fetch_revision_only = enabled;
+        self
+    }
+
+    /// Keep a shared bare mirror of the repository within the given directory and check out from it,
+    /// so the repository only needs to be fetched once for all builds on this machine.
+    /// If not set, the `rbuild_git_mirror_dir` environment variable will be used.
+    pub fn mirror_folder(mut self, path: Option<PathBuf>) -> Self {
+        self.inner.mirror_folder = path;
+        self
+    }
+
+    /// Only accept a full 40 character commit SHA as revision and verify that it has been checked out.
+    /// If a tag is given as well, it must point to the pinned revision.
+    pub fn require_pinned_revision(mut self, enabled: bool) -> Self {
+        self.inner.require_pinned_revision = enabled;
+        self
+    }
+
+    /// Verify the signature of the checked out tag (`git verify-tag`) or commit (`git verify-commit`).
+    /// The keyring must be a GnuPG home directory containing the trusted public keys.
+    pub fn signature_keyring(mut self, path: Option<PathBuf>) -> Self {
+        self.inner.signature_keyring = path;
+        self
+    }
+
+    /// Remove all untracked and ignored files (`git clean -fdx`) of the checkout and its submodules
+    pub fn clean_worktree(mut self, enabled: bool) -> Self {
+        self.inner.clean_worktree = enabled;
+        self
+    }
+
+    /// Fail instead of overwriting local modifications of a reused checkout (e.g. a working copy).
+    /// Untracked files are only considered if the worktree will be cleaned.
+    /// Modifications made by wrapping sources (e.g. patches applied by `BuildSourcePatched`) are not considered local modifications.
+    pub fn refuse_local_modifications(mut self, enabled: bool) -> Self {
+        self.inner.refuse_local_modifications = enabled;
+        self
+    }
+
+    /// Pass a git configuration option (`-c <key>=<value>`) to every git command of the source.
+    /// The options don't affect the checked out content and are not part of the source hash.
+    pub fn git_config(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.inner.git_config.insert(key.into(), value.into());
+        self
+    }
+
+    /// Never access the network and only use an existing checkout or mirror.
+    /// If not set, the `rbuild_offline` environment variable will be used.
+    pub fn offline(mut self, enabled: Option<bool>) -> Self {
+        self.inner.offline = enabled;
+        self
+    }
+
     pub fn build(self) -> BuildSourceGit {
         self.inner
     }
@@ -217,7 +1001,9 @@ impl BuildSourceGitBuilder {
 
 #[cfg(test)]
 mod test {
-    use crate::source::{BuildSourceGit, BuildSource};
+    use crate::source::{BuildSourceGit, BuildSource, SourceSetupError, BuildSourcePatched};
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
 
     #[test]
     fn test_git() {
@@ -226,4 +1012,548 @@ mod test {
 
         source.setup().unwrap();
     }
-}
\ No newline at end of file
+
+    fn git(directory: &Path, args: &[&str]) -> String {
+        let output = Command::new("git")
+            .args(["-c", "user.name=test", "-c", "user.email=test@localhost", "-c", "protocol.file.allow=always"])
+            .args(args)
+            .current_dir(directory)
+            .output()
+            .expect("failed to execute git");
+        assert!(output.status.success(), "git {:?} failed: {}", args, String::from_utf8_lossy(&output.stderr));
+        String::from_utf8_lossy(&output.stdout).trim().to_owned()
+    }
+
+    /// Create a local repository with one commit containing the given file
+    fn create_repository(path: &Path, file_name: &str) -> PathBuf {
+        let _ = std::fs::remove_dir_all(path);
+        std::fs::create_dir_all(path).unwrap();
+        git(path, &["init", "-q"]);
+        std::fs::write(path.join(file_name), file_name).unwrap();
+        git(path, &["add", "."]);
+        git(path, &["commit", "-q", "-m", "initial commit"]);
+        path.to_owned()
+    }
+
+    #[test]
+    fn test_git_submodules() {
+        let base_url = std::env::temp_dir().join("__test_git_submodules");
+        let _ = std::fs::remove_dir_all(&base_url);
+
+        let submodule = create_repository(&base_url.join("submodule"), "submodule.txt");
+        let other_submodule = create_repository(&base_url.join("other"), "other.txt");
+        let upstream = create_repository(&base_url.join("upstream"), "upstream.txt");
+        git(&upstream, &["submodule", "add", "-q", submodule.to_str().unwrap(), "sub"]);
+        git(&upstream, &["submodule", "add", "-q", other_submodule.to_str().unwrap(), "other"]);
+        git(&upstream, &["commit", "-q", "-m", "add submodules"]);
+
+        let mut source = BuildSourceGit::builder(upstream.to_string_lossy().into_owned())
+            .checkout_folder(Some(base_url.join("checkout")))
+            .checkout_submodule(true)
+            .submodule_paths(vec!["sub".to_owned()])
+            /* submodules with a local file url are only allowed with protocol.file.allow */
+            .git_config("protocol.file.allow", "always")
+            .build();
+
+        source.setup().expect("failed to setup git source");
+        assert!(source.local_directory().join("sub").join("submodule.txt").is_file());
+        assert!(!source.local_directory().join("other").join("other.txt").exists());
+
+        /* updating the existing checkout should initialize the submodules as well */
+        source.cleanup();
+        std::fs::remove_dir_all(source_checkout(&base_url).join("sub")).unwrap();
+        source.setup().expect("failed to update git source");
+        assert!(source.local_directory().join("sub").join("submodule.txt").is_file());
+
+        /* in offline mode only submodules which are available locally can be initialized */
+        let create_offline_source = |paths: Vec<String>| {
+            BuildSourceGit::builder(upstream.to_string_lossy().into_owned())
+                .checkout_folder(Some(base_url.join("checkout")))
+                .checkout_submodule(true)
+                .submodule_paths(paths)
+                .git_config("protocol.file.allow", "always")
+                .offline(Some(true))
+                .build()
+        };
+
+        std::fs::remove_dir_all(source_checkout(&base_url).join("sub")).unwrap();
+        let mut source = create_offline_source(vec!["sub".to_owned()]);
+        source.setup().expect("the existing submodule should be available offline");
+        assert!(source.local_directory().join("sub").join("submodule.txt").is_file());
+
+        let mut source = create_offline_source(Vec::new());
+        let error = source.setup().expect_err("the other submodule should not be available offline");
+        assert!(format!("{:?}", error).contains("offline mode: git submodule other"));
+        assert!(!source.local_directory().join("other").join("other.txt").exists());
+
+        std::fs::remove_dir_all(&base_url).unwrap();
+    }
+
+    fn source_checkout(base_url: &Path) -> PathBuf {
+        std::fs::read_dir(base_url.join("checkout")).unwrap()
+            .next().unwrap().unwrap()
+            .path()
+    }
+
+    #[test]
+    fn test_git_branch_and_tag() {
+        let base_url = std::env::temp_dir().join("__test_git_branch_and_tag");
+        let _ = std::fs::remove_dir_all(&base_url);
+
+        let upstream = create_repository(&base_url.join("upstream"), "upstream.txt");
+        git(&upstream, &["tag", "v1.0"]);
+        let tag_commit = git(&upstream, &["rev-parse", "HEAD"]);
+        git(&upstream, &["checkout", "-q", "-b", "feature"]);
+        std::fs::write(upstream.join("feature.txt"), "feature").unwrap();
+        git(&upstream, &["add", "."]);
+        git(&upstream, &["commit", "-q", "-m", "feature commit"]);
+
+        let create_source = |branch: Option<&str>, tag: Option<&str>| {
+            BuildSourceGit::builder(upstream.to_string_lossy().into_owned())
+                .checkout_folder(Some(base_url.join("checkout")))
+                .branch(branch.map(|branch| branch.to_owned()))
+                .tag(tag.map(|tag| tag.to_owned()))
+                .build()
+        };
+
+        let mut source = create_source(Some("feature"), None);
+        source.setup().expect("failed to setup git source");
+        assert!(source.local_directory().join("feature.txt").is_file());
+        assert_eq!(source.resolved_revision(), Some(git(&upstream, &["rev-parse", "HEAD"]).as_str()));
+
+        /* a new commit on the branch must be picked up by the existing checkout */
+        std::fs::write(upstream.join("feature.txt"), "feature update").unwrap();
+        git(&upstream, &["commit", "-q", "-am", "feature update"]);
+
+        let mut source = create_source(Some("feature"), None);
+        source.setup().expect("failed to update git source");
+        assert_eq!(std::fs::read_to_string(source.local_directory().join("feature.txt")).unwrap(), "feature update");
+        assert_eq!(source.resolved_revision(), Some(git(&upstream, &["rev-parse", "HEAD"]).as_str()));
+
+        let mut source = create_source(None, Some("v1.0"));
+        source.setup().expect("failed to checkout tag");
+        assert!(!source.local_directory().join("feature.txt").exists());
+        assert_eq!(source.resolved_revision(), Some(tag_commit.as_str()));
+
+        let mut source = create_source(Some("missing"), None);
+        assert!(source.setup().is_err());
+
+        std::fs::remove_dir_all(&base_url).unwrap();
+    }
+
+    #[test]
+    fn test_git_shallow_clone() {
+        let base_url = std::env::temp_dir().join("__test_git_shallow_clone");
+        let _ = std::fs::remove_dir_all(&base_url);
+
+        let upstream = create_repository(&base_url.join("upstream"), "upstream.txt");
+        let first_commit = git(&upstream, &["rev-parse", "HEAD"]);
+        for index in 0..2 {
+            std::fs::write(upstream.join("upstream.txt"), format!("update {}", index)).unwrap();
+            git(&upstream, &["commit", "-q", "-am", "update"]);
+        }
+
+        /* --depth is ignored for local paths */
+        let upstream_url = format!("file://{}", upstream.to_string_lossy());
+        let history_length = |source: &BuildSourceGit| git(source.local_directory(), &["rev-list", "--count", "HEAD"]);
+
+        let mut source = BuildSourceGit::builder(upstream_url.clone())
+            .checkout_folder(Some(base_url.join("checkout_depth")))
+            .clone_depth(Some(1))
+            .single_branch(true)
+            .blobless(true)
+            .build();
+        source.setup().expect("failed to setup shallow git source");
+        assert_eq!(history_length(&source), "1");
+        assert_eq!(source.resolved_revision(), Some(git(&upstream, &["rev-parse", "HEAD"]).as_str()));
+
+        /* a full clone must not reuse the shallow single branch checkout */
+        let mut full_source = BuildSourceGit::builder(upstream_url.clone())
+            .checkout_folder(Some(base_url.join("checkout_depth")))
+            .build();
+        full_source.setup().expect("failed to setup full git source");
+        assert_ne!(full_source.local_directory(), source.local_directory());
+        assert_eq!(history_length(&full_source), "3");
+
+        let mut source = BuildSourceGit::builder(upstream_url.clone())
+            .checkout_folder(Some(base_url.join("checkout_pinned")))
+            .revision(Some(first_commit.clone()))
+            .fetch_revision_only(true)
+            .clone_depth(Some(1))
+            .build();
+        source.setup().expect("failed to setup pinned git source");
+        assert_eq!(history_length(&source), "1");
+        assert_eq!(source.resolved_revision(), Some(first_commit.as_str()));
+
+        /* the revision isn't part of the shallow history and requires a full fetch */
+        let mut source = BuildSourceGit::builder(upstream_url)
+            .checkout_folder(Some(base_url.join("checkout_unshallow")))
+            .revision(Some(first_commit.clone()))
+            .clone_depth(Some(1))
+            .build();
+        source.setup().expect("failed to setup shallow git source with an old revision");
+        assert_eq!(source.resolved_revision(), Some(first_commit.as_str()));
+
+        std::fs::remove_dir_all(&base_url).unwrap();
+    }
+
+    #[test]
+    fn test_git_fetch_revision_fallback() {
+        let base_url = std::env::temp_dir().join("__test_git_fetch_revision_fallback");
+        let _ = std::fs::remove_dir_all(&base_url);
+
+        let upstream = create_repository(&base_url.join("upstream"), "upstream.txt");
+        let first_commit = git(&upstream, &["rev-parse", "HEAD"]);
+        std::fs::write(upstream.join("upstream.txt"), "update").unwrap();
+        git(&upstream, &["commit", "-q", "-am", "update"]);
+
+        /* the first commit isn't advertised and the server refuses to send unadvertised commits */
+        git(&base_url, &["clone", "-q", "--bare", upstream.to_str().unwrap(), "remote.git"]);
+        git(&base_url.join("remote.git"), &["config", "uploadpack.allowReachableSHA1InWant", "false"]);
+        git(&base_url.join("remote.git"), &["config", "uploadpack.allowAnySHA1InWant", "false"]);
+
+        let mut source = BuildSourceGit::builder(format!("file://{}", base_url.join("remote.git").to_string_lossy()))
+            .checkout_folder(Some(base_url.join("checkout")))
+            .revision(Some(first_commit.clone()))
+            .fetch_revision_only(true)
+            .clone_depth(Some(1))
+            /* protocol v2 always allows fetching reachable commits */
+            .git_config("protocol.version", "0")
+            .build();
+        source.setup().expect("failed to setup pinned git source with the fallback fetch");
+        assert_eq!(source.resolved_revision(), Some(first_commit.as_str()));
+        assert_eq!(std::fs::read_to_string(source.local_directory().join("upstream.txt")).unwrap(), "upstream.txt");
+
+        std::fs::remove_dir_all(&base_url).unwrap();
+    }
+
+    #[test]
+    fn test_git_mirror() {
+        let base_url = std::env::temp_dir().join("__test_git_mirror");
+        let _ = std::fs::remove_dir_all(&base_url);
+
+        let upstream = create_repository(&base_url.join("upstream"), "upstream.txt");
+        let create_source = |checkout: &str| {
+            BuildSourceGit::builder(upstream.to_string_lossy().into_owned())
+                .checkout_folder(Some(base_url.join(checkout)))
+                .mirror_folder(Some(base_url.join("mirrors")))
+                .build()
+        };
+
+        let mut source = create_source("checkout_a");
+        source.setup().expect("failed to setup mirrored git source");
+        assert!(source.local_directory().join("upstream.txt").is_file());
+
+        let mirrors = std::fs::read_dir(base_url.join("mirrors")).unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect::<Vec<_>>();
+        assert_eq!(mirrors.len(), 1);
+        assert!(mirrors[0].file_name().unwrap().to_string_lossy().starts_with("upstream_"));
+        /* the origin has to point to the repository itself, so relative submodule urls can be resolved */
+        assert_eq!(git(source.local_directory(), &["remote", "get-url", "origin"]), upstream.to_string_lossy());
+
+        /* a second checkout should use the same mirror and receive new upstream commits through it */
+        std::fs::write(upstream.join("upstream.txt"), "update").unwrap();
+        git(&upstream, &["commit", "-q", "-am", "update"]);
+
+        let mut source = create_source("checkout_b");
+        source.setup().expect("failed to setup mirrored git source");
+        assert_eq!(source.resolved_revision(), Some(git(&upstream, &["rev-parse", "HEAD"]).as_str()));
+        assert_eq!(std::fs::read_dir(base_url.join("mirrors")).unwrap().count(), 1);
+
+        let mut source = create_source("checkout_a");
+        source.setup().expect("failed to update mirrored git source");
+        assert_eq!(std::fs::read_to_string(source.local_directory().join("upstream.txt")).unwrap(), "update");
+
+        std::fs::remove_dir_all(&base_url).unwrap();
+    }
+
+    #[test]
+    fn test_git_mirror_relative_submodules() {
+        let base_url = std::env::temp_dir().join("__test_git_mirror_relative_submodules");
+        let _ = std::fs::remove_dir_all(&base_url);
+
+        create_repository(&base_url.join("submodule"), "submodule.txt");
+        let upstream = create_repository(&base_url.join("upstream"), "upstream.txt");
+        git(&upstream, &["submodule", "add", "-q", "../submodule", "sub"]);
+        git(&upstream, &["commit", "-q", "-m", "add submodule"]);
+
+        /* the relative url must be resolved against the upstream repository and not the mirror */
+        let mut source = BuildSourceGit::builder(upstream.to_string_lossy().into_owned())
+            .checkout_folder(Some(base_url.join("checkout")))
+            .mirror_folder(Some(base_url.join("mirrors")))
+            .checkout_submodule(true)
+            .git_config("protocol.file.allow", "always")
+            .build();
+        source.setup().expect("failed to setup mirrored git source with submodules");
+        assert!(source.local_directory().join("sub").join("submodule.txt").is_file());
+
+        std::fs::remove_dir_all(&base_url).unwrap();
+    }
+
+    #[test]
+    fn test_git_offline() {
+        let base_url = std::env::temp_dir().join("__test_git_offline");
+        let _ = std::fs::remove_dir_all(&base_url);
+
+        let upstream = create_repository(&base_url.join("upstream"), "upstream.txt");
+        let first_commit = git(&upstream, &["rev-parse", "HEAD"]);
+        let create_source = |offline: bool, tag: Option<&str>| {
+            BuildSourceGit::builder(upstream.to_string_lossy().into_owned())
+                .checkout_folder(Some(base_url.join("checkout")))
+                .tag(tag.map(|tag| tag.to_owned()))
+                .offline(Some(offline))
+                .build()
+        };
+
+        let mut source = create_source(true, None);
+        let error = source.setup().expect_err("offline setup without a checkout should fail");
+        assert!(format!("{:?}", error).contains("offline mode: neither a checkout nor a mirror"));
+
+        create_source(false, None).setup().expect("failed to setup git source");
+
+        std::fs::write(upstream.join("upstream.txt"), "update").unwrap();
+        git(&upstream, &["commit", "-q", "-am", "update"]);
+        git(&upstream, &["tag", "v2"]);
+
+        /* the new commit must not be fetched */
+        let mut source = create_source(true, None);
+        source.setup().expect("failed to setup offline git source");
+        assert_eq!(source.resolved_revision(), Some(first_commit.as_str()));
+
+        /* the tag is checked out into its own checkout, which doesn't exist yet */
+        let mut source = create_source(true, Some("v2"));
+        let error = source.setup().expect_err("the new tag should not be available offline");
+        assert!(matches!(error, SourceSetupError::SourceDoesNotExists(_)));
+        assert!(format!("{:?}", error).contains("offline mode: neither a checkout nor a mirror"));
+
+        /* a new checkout can be cloned from an existing mirror, but the mirror must not be updated */
+        let create_mirrored_source = |offline: bool, tag: &str| {
+            BuildSourceGit::builder(upstream.to_string_lossy().into_owned())
+                .checkout_folder(Some(base_url.join("checkout")))
+                .mirror_folder(Some(base_url.join("mirror")))
+                .tag(Some(tag.to_owned()))
+                .offline(Some(offline))
+                .build()
+        };
+        create_mirrored_source(false, "v2").setup().expect("failed to setup mirrored git source");
+        git(&upstream, &["tag", "v3"]);
+
+        let mut source = create_mirrored_source(true, "v3");
+        let error = source.setup().expect_err("the new tag should not be available offline");
+        assert!(matches!(error, SourceSetupError::RevisionNotFound(_)));
+        assert!(format!("{:?}", error).contains("is not available locally"));
+
+        std::fs::remove_dir_all(&base_url).unwrap();
+    }
+
+    #[test]
+    fn test_git_clean_worktree() {
+        let base_url = std::env::temp_dir().join("__test_git_clean_worktree");
+        let _ = std::fs::remove_dir_all(&base_url);
+
+        let submodule = create_repository(&base_url.join("submodule"), "submodule.txt");
+        let upstream = create_repository(&base_url.join("upstream"), "upstream.txt");
+        git(&upstream, &["submodule", "add", "-q", submodule.to_str().unwrap(), "sub"]);
+        git(&upstream, &["commit", "-q", "-m", "add submodule"]);
+
+        let create_source = |clean: bool, refuse: bool| {
+            BuildSourceGit::builder(upstream.to_string_lossy().into_owned())
+                .checkout_folder(Some(base_url.join("checkout")))
+                .checkout_submodule(true)
+                .clean_worktree(clean)
+                .refuse_local_modifications(refuse)
+                .git_config("protocol.file.allow", "always")
+                .build()
+        };
+
+        let mut source = create_source(true, false);
+        source.setup().expect("failed to setup git source");
+        let checkout = source.local_directory().clone();
+        std::fs::write(checkout.join("junk.txt"), "junk").unwrap();
+        std::fs::write(checkout.join("sub").join("junk.txt"), "junk").unwrap();
+
+        /* untracked files are only relevant if the worktree will be cleaned */
+        create_source(false, true).setup().expect("untracked files should not prevent the setup");
+        assert!(checkout.join("junk.txt").is_file());
+        let error = create_source(true, true).setup().expect_err("untracked files would be removed");
+        assert!(matches!(error, SourceSetupError::LocalModifications(_)));
+
+        create_source(true, false).setup().expect("failed to setup git source");
+        assert!(!checkout.join("junk.txt").exists());
+        assert!(!checkout.join("sub").join("junk.txt").exists());
+        assert!(checkout.join("sub").join("submodule.txt").is_file());
+
+        std::fs::write(checkout.join("upstream.txt"), "local change").unwrap();
+        let error = create_source(false, true).setup().expect_err("local modifications must not be overwritten");
+        assert!(matches!(error, SourceSetupError::LocalModifications(_)));
+        assert_eq!(std::fs::read_to_string(checkout.join("upstream.txt")).unwrap(), "local change");
+
+        std::fs::remove_dir_all(&base_url).unwrap();
+    }
+
+    #[test]
+    fn test_git_refuse_local_modifications_patched() {
+        let base_url = std::env::temp_dir().join("__test_git_refuse_local_modifications_patched");
+        let _ = std::fs::remove_dir_all(&base_url);
+
+        let upstream = create_repository(&base_url.join("upstream"), "upstream.txt");
+        std::fs::write(base_url.join("fix.patch"), "--- a/upstream.txt\n+++ b/upstream.txt\n@@ -1 +1 @@\n-upstream.txt\n\\ No newline at end of file\n+patched\n").unwrap();
+
+        let create_source = || {
+            let source = BuildSourceGit::builder(upstream.to_string_lossy().into_owned())
+                .checkout_folder(Some(base_url.join("checkout")))
+                .clean_worktree(true)
+                .refuse_local_modifications(true)
+                .build();
+            BuildSourcePatched::builder(Box::new(source))
+                .patch(base_url.join("fix.patch"))
+                .build()
+        };
+
+        let mut source = create_source();
+        source.setup().expect("failed to setup patched git source");
+        let checkout = source.local_directory().clone();
+        assert_eq!(std::fs::read_to_string(checkout.join("upstream.txt")).unwrap(), "patched\n");
+
+        /* the applied patches are no local modifications */
+        create_source().setup().expect("failed to reuse patched git source");
+        assert_eq!(std::fs::read_to_string(checkout.join("upstream.txt")).unwrap(), "patched\n");
+
+        std::fs::write(checkout.join("upstream.txt"), "local change").unwrap();
+        let error = create_source().setup().expect_err("local modifications must not be overwritten");
+        assert!(matches!(error, SourceSetupError::LocalModifications(_)));
+
+        std::fs::write(checkout.join("upstream.txt"), "patched\n").unwrap();
+        std::fs::write(checkout.join("junk.txt"), "junk").unwrap();
+        let error = create_source().setup().expect_err("untracked files would be removed");
+        assert!(matches!(error, SourceSetupError::LocalModifications(_)));
+
+        std::fs::remove_dir_all(&base_url).unwrap();
+    }
+
+    #[test]
+    fn test_git_pinned_revision() {
+        let base_url = std::env::temp_dir().join("__test_git_pinned_revision");
+        let _ = std::fs::remove_dir_all(&base_url);
+
+        let upstream = create_repository(&base_url.join("upstream"), "upstream.txt");
+        git(&upstream, &["tag", "v1.0"]);
+        let tag_commit = git(&upstream, &["rev-parse", "HEAD"]);
+        std::fs::write(upstream.join("upstream.txt"), "update").unwrap();
+        git(&upstream, &["commit", "-q", "-am", "update"]);
+        let head_commit = git(&upstream, &["rev-parse", "HEAD"]);
+
+        let create_source = |revision: &str, tag: Option<&str>| {
+            BuildSourceGit::builder(upstream.to_string_lossy().into_owned())
+                .checkout_folder(Some(base_url.join("checkout")))
+                .revision(Some(revision.to_owned()))
+                .tag(tag.map(|tag| tag.to_owned()))
+                .require_pinned_revision(true)
+                .build()
+        };
+
+        let error = create_source(&tag_commit[..12], None).setup().expect_err("abbreviated revisions must be rejected");
+        assert!(matches!(error, SourceSetupError::VerificationFailed(_)));
+
+        let mut source = create_source(&tag_commit, Some("v1.0"));
+        source.setup().expect("failed to setup pinned git source");
+        assert_eq!(source.resolved_revision(), Some(tag_commit.as_str()));
+
+        let error = create_source(&head_commit, Some("v1.0")).setup().expect_err("the tag doesn't point to the pinned revision");
+        assert!(format!("{:?}", error).contains("instead of the pinned revision"));
+
+        /* the tag moves to the latest commit */
+        git(&upstream, &["tag", "-f", "v1.0"]);
+        let mut source = BuildSourceGit::builder(upstream.to_string_lossy().into_owned())
+            .checkout_folder(Some(base_url.join("checkout_tag")))
+            .tag(Some("v1.0".to_owned()))
+            .build();
+        source.setup().expect("failed to setup git tag source");
+
+        git(&upstream, &["tag", "-f", "v1.0", &tag_commit]);
+        let mut source = BuildSourceGit::builder(upstream.to_string_lossy().into_owned())
+            .checkout_folder(Some(base_url.join("checkout_tag")))
+            .tag(Some("v1.0".to_owned()))
+            .build();
+        let error = source.setup().expect_err("a moved tag must fail the setup");
+        assert!(matches!(error, SourceSetupError::VerificationFailed(_)));
+        assert!(format!("{:?}", error).contains("has been moved"));
+
+        /* the record of the tag survives a fresh clone */
+        let checkout = std::fs::read_dir(base_url.join("checkout_tag")).unwrap()
+            .map(|entry| entry.unwrap().path())
+            .find(|path| path.is_dir())
+            .unwrap();
+        std::fs::remove_dir_all(&checkout).unwrap();
+        let mut source = BuildSourceGit::builder(upstream.to_string_lossy().into_owned())
+            .checkout_folder(Some(base_url.join("checkout_tag")))
+            .tag(Some("v1.0".to_owned()))
+            .build();
+        let error = source.setup().expect_err("a moved tag must fail the setup of a fresh clone");
+        assert!(format!("{:?}", error).contains("has been moved"));
+
+        /* removing the record accepts the moved tag */
+        std::fs::remove_file(BuildSourceGit::tag_record_path(&checkout)).unwrap();
+        let mut source = BuildSourceGit::builder(upstream.to_string_lossy().into_owned())
+            .checkout_folder(Some(base_url.join("checkout_tag")))
+            .tag(Some("v1.0".to_owned()))
+            .build();
+        source.setup().expect("failed to setup git tag source after accepting the moved tag");
+        assert_eq!(source.resolved_revision(), Some(tag_commit.as_str()));
+
+        std::fs::remove_dir_all(&base_url).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_git_signature_verification() {
+        let base_url = std::env::temp_dir().join("__test_git_signature");
+        let _ = std::fs::remove_dir_all(&base_url);
+
+        let keyring = base_url.join("gnupg");
+        std::fs::create_dir_all(&keyring).unwrap();
+        std::fs::set_permissions(&keyring, std::os::unix::fs::PermissionsExt::from_mode(0o700)).unwrap();
+
+        let status = Command::new("gpg")
+            .args(["--batch", "--quiet", "--passphrase", "", "--quick-gen-key", "test <test@localhost>", "default", "default", "never"])
+            .env("GNUPGHOME", &keyring)
+            .status()
+            .expect("failed to execute gpg");
+        assert!(status.success());
+
+        let upstream = create_repository(&base_url.join("upstream"), "upstream.txt");
+        let create_source = |checkout: &str| {
+            BuildSourceGit::builder(upstream.to_string_lossy().into_owned())
+                .checkout_folder(Some(base_url.join(checkout)))
+                .signature_keyring(Some(keyring.clone()))
+                .build()
+        };
+
+        let error = create_source("checkout_unsigned").setup().expect_err("unsigned commits must be rejected");
+        assert!(matches!(error, SourceSetupError::VerificationFailed(_)));
+
+        std::fs::write(upstream.join("upstream.txt"), "signed").unwrap();
+        let status = Command::new("git")
+            .args(["-c", "user.name=test", "-c", "user.email=test@localhost", "-c", "user.signingkey=test@localhost"])
+            .args(["commit", "-q", "-S", "-am", "signed commit"])
+            .env("GNUPGHOME", &keyring)
+            .current_dir(&upstream)
+            .status()
+            .expect("failed to execute git");
+        assert!(status.success());
+
+        create_source("checkout_signed").setup().expect("signed commit should be verified");
+
+        /* an unsigned update must be rejected before it gets checked out */
+        std::fs::write(upstream.join("upstream.txt"), "unsigned").unwrap();
+        git(&upstream, &["commit", "-q", "-am", "unsigned commit"]);
+        let mut source = create_source("checkout_signed");
+        let error = source.setup().expect_err("unsigned commits must be rejected");
+        assert!(matches!(error, SourceSetupError::VerificationFailed(_)));
+        assert_eq!(std::fs::read_to_string(source.local_directory().join("upstream.txt")).unwrap(), "signed");
+
+        let _ = Command::new("gpgconf").args(["--kill", "gpg-agent"]).env("GNUPGHOME", &keyring).status();
+        std::fs::remove_dir_all(&base_url).unwrap();
+    }
+}
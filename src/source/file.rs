@@ -1,7 +1,9 @@
-use crate::source::{BuildSource};
-use std::path::PathBuf;
-use crate::build::BuildStepError;
+use crate::source::{BuildSource, SourceSetupError};
+use crate::source::archive::hash_file_content;
+use std::path::{PathBuf, Path};
 use std::hash::{Hasher, Hash};
+use std::time::UNIX_EPOCH;
+use crate::build::BuildStepError;
 
 #[derive(Debug, Ord, PartialOrd, Eq, PartialEq, Copy, Clone)]
 pub enum BuildSourceDirectoryError {
@@ -10,20 +12,104 @@ pub enum BuildSourceDirectoryError {
     DirectoryNotAccessable
 }
 
+/// Which information of the files within the directory will be used to detect changes.
+/// A changed directory re-executes the build steps, but doesn't change the build hash and therefore the build directory.
+#[derive(Debug, Ord, PartialOrd, Eq, PartialEq, Copy, Clone, Hash)]
+pub enum DirectoryFingerprint {
+    /// Changes within the directory will not be detected, the build steps will always be executed
+    None,
+    /// The relative path, size and modification time of every file will be hashed
+    Metadata,
+    /// The relative path and the content of every file will be hashed
+    Contents
+}
+
 pub struct BuildSourceDirectory {
-    path: PathBuf
+    path: PathBuf,
+    fingerprint: DirectoryFingerprint,
+    ignore_patterns: Vec<String>
 }
 
 impl BuildSourceDirectory {
     pub fn new(target: PathBuf) -> Result<Self, BuildSourceDirectoryError> {
-        if target.exists() {
-            Err(BuildSourceDirectoryError::TargetDoesNotExists)
-        } else if !target.is_dir() {
-            Err(BuildSourceDirectoryError::TargetIsNotADirectory)
-        } else if let Ok(_) = target.read_dir() {
-            Err(BuildSourceDirectoryError::DirectoryNotAccessable)
-        } else {
-            Ok(BuildSourceDirectory{ path: target })
+        Self::builder(target).build()
+    }
+
+    pub fn builder(target: PathBuf) -> BuildSourceDirectoryBuilder {
+        BuildSourceDirectoryBuilder::new(target)
+    }
+
+    fn is_ignored(&self, relative_path: &str) -> bool {
+        self.ignore_patterns.iter().any(|pattern| {
+            if pattern.contains('/') {
+                glob_matches(pattern.as_bytes(), relative_path.as_bytes())
+            } else {
+                /* patterns without a slash match the file name at any depth */
+                relative_path.rsplit('/').next()
+                    .map(|name| glob_matches(pattern.as_bytes(), name.as_bytes()))
+                    .unwrap_or(false)
+            }
+        })
+    }
+
+    fn hash_directory(&self, directory: &Path, relative_path: &str, target: &mut Box<dyn Hasher>) -> std::io::Result<()> {
+        let mut entries = std::fs::read_dir(directory)?
+            .collect::<Result<Vec<_>, _>>()?;
+        /* the order of read_dir isn't specified */
+        entries.sort_by_key(|entry| entry.file_name());
+
+        for entry in entries {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let entry_path = if relative_path.is_empty() { name } else { format!("{}/{}", relative_path, name) };
+            if self.is_ignored(&entry_path) {
+                continue;
+            }
+
+            /* symlinks will not be followed */
+            let metadata = std::fs::symlink_metadata(entry.path())?;
+            entry_path.hash(target);
+            if metadata.is_dir() {
+                self.hash_directory(&entry.path(), &entry_path, target)?;
+            } else if metadata.file_type().is_symlink() {
+                std::fs::read_link(entry.path())?.hash(target);
+            } else if self.fingerprint == DirectoryFingerprint::Contents {
+                hash_file_content(&entry.path(), target)?;
+            } else {
+                metadata.len().hash(target);
+                metadata.modified()?
+                    .duration_since(UNIX_EPOCH)
+                    .map(|duration| duration.as_nanos())
+                    .unwrap_or(0)
+                    .hash(target);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Match a path against a glob pattern.
+/// `*` and `?` don't match a `/`, `**` matches any number of path components.
+fn glob_matches(pattern: &[u8], path: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((b'*', remaining)) if remaining.first() == Some(&b'*') => {
+            let remaining = &remaining[1..];
+            /* a leading double star followed by a slash may match no directory at all */
+            if remaining.first() == Some(&b'/') && glob_matches(&remaining[1..], path) {
+                return true;
+            }
+            (0..=path.len()).any(|index| glob_matches(remaining, &path[index..]))
+        },
+        Some((b'*', remaining)) => {
+            (0..=path.len())
+                .take_while(|index| *index == 0 || path[index - 1] != b'/')
+                .any(|index| glob_matches(remaining, &path[index..]))
+        },
+        Some((b'?', remaining)) => {
+            matches!(path.first(), Some(character) if *character != b'/') && glob_matches(remaining, &path[1..])
+        },
+        Some((character, remaining)) => {
+            path.first() == Some(character) && glob_matches(remaining, &path[1..])
         }
     }
 }
@@ -37,7 +123,7 @@ impl BuildSource for BuildSourceDirectory {
         self.path.hash(target);
     }
 
-    fn setup(&mut self) -> Result<(), BuildStepError> {
+    fn setup(&mut self) -> Result<(), SourceSetupError> {
         Ok(())
     }
 
@@ -45,5 +131,139 @@ impl BuildSource for BuildSourceDirectory {
         &self.path
     }
 
+    fn fingerprint(&self, target: &mut Box<dyn Hasher>) -> Result<bool, SourceSetupError> {
+        if self.fingerprint == DirectoryFingerprint::None {
+            return Ok(false);
+        }
+
+        self.fingerprint.hash(target);
+        self.hash_directory(&self.path, "", target)
+            .map_err(|error| SourceSetupError::SourceIsNotReadable(BuildStepError::new_io(format!("failed to fingerprint source directory {:?}", &self.path), error)))?;
+        Ok(true)
+    }
+
     fn cleanup(&mut self) { }
-}
\ No newline at end of file
+
+    fn rerun_if_changed(&self) -> Vec<PathBuf> {
+        vec![self.path.clone()]
+    }
+}
+
+pub struct BuildSourceDirectoryBuilder {
+    inner: BuildSourceDirectory
+}
+
+impl BuildSourceDirectoryBuilder {
+    fn new(target: PathBuf) -> Self {
+        BuildSourceDirectoryBuilder {
+            inner: BuildSourceDirectory {
+                path: target,
+                fingerprint: DirectoryFingerprint::None,
+                ignore_patterns: Vec::new()
+            }
+        }
+    }
+
+    /// Set which file information will be used to detect changes within the directory (default: `None`)
+    pub fn fingerprint(mut self, fingerprint: DirectoryFingerprint) -> Self {
+        self.inner.fingerprint = fingerprint;
+        self
+    }
+
+    /// Exclude matching files and directories from the fingerprint (e.g. `.git`, `*.o` or `build/**`).
+    /// Patterns are matched against the path relative to the source directory, using `/` as separator.
+    pub fn ignore<V>(mut self, pattern: V) -> Self
+        where V: Into<String>
+    {
+        self.inner.ignore_patterns.push(pattern.into());
+        self
+    }
+
+    pub fn build(self) -> Result<BuildSourceDirectory, BuildSourceDirectoryError> {
+        let target = &self.inner.path;
+        if !target.exists() {
+            Err(BuildSourceDirectoryError::TargetDoesNotExists)
+        } else if !target.is_dir() {
+            Err(BuildSourceDirectoryError::TargetIsNotADirectory)
+        } else if target.read_dir().is_err() {
+            Err(BuildSourceDirectoryError::DirectoryNotAccessable)
+        } else {
+            Ok(self.inner)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::source::{BuildSourceDirectory, BuildSourceDirectoryError, BuildSource, DirectoryFingerprint};
+    use crate::source::file::glob_matches;
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::Hasher;
+
+    fn source_hash(source: &BuildSourceDirectory) -> u64 {
+        let mut hasher: Box<dyn Hasher> = Box::new(DefaultHasher::new());
+        source.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn source_fingerprint(source: &BuildSourceDirectory) -> Option<u64> {
+        let mut hasher: Box<dyn Hasher> = Box::new(DefaultHasher::new());
+        if !source.fingerprint(&mut hasher).expect("failed to fingerprint source") {
+            return None;
+        }
+        Some(hasher.finish())
+    }
+
+    #[test]
+    fn test_glob_matches() {
+        assert!(glob_matches(b"*.o", b"agent.o"));
+        assert!(!glob_matches(b"*.o", b"src/agent.o"));
+        assert!(glob_matches(b"src/?gent.c", b"src/agent.c"));
+        assert!(glob_matches(b"build/**", b"build/meson-logs/log.txt"));
+        assert!(glob_matches(b"**/*.o", b"agent.o"));
+        assert!(glob_matches(b"**/*.o", b"src/agent/agent.o"));
+        assert!(!glob_matches(b"src/*", b"src/agent/agent.c"));
+    }
+
+    #[test]
+    fn test_directory_source() {
+        let base_url = std::env::temp_dir().join("__test_directory_source");
+        let _ = std::fs::remove_dir_all(&base_url);
+        std::fs::create_dir_all(base_url.join("src")).unwrap();
+        std::fs::write(base_url.join("src").join("agent.c"), "int main() {}").unwrap();
+
+        assert_eq!(BuildSourceDirectory::new(base_url.join("missing")).err(), Some(BuildSourceDirectoryError::TargetDoesNotExists));
+        assert_eq!(BuildSourceDirectory::new(base_url.join("src").join("agent.c")).err(), Some(BuildSourceDirectoryError::TargetIsNotADirectory));
+
+        let create_source = |fingerprint| {
+            BuildSourceDirectory::builder(base_url.clone())
+                .fingerprint(fingerprint)
+                .ignore("*.o")
+                .build()
+                .expect("failed to create directory source")
+        };
+
+        /* without a fingerprint the build steps will always be executed */
+        assert_eq!(source_fingerprint(&create_source(DirectoryFingerprint::None)), None);
+
+        let metadata_fingerprint = source_fingerprint(&create_source(DirectoryFingerprint::Metadata));
+        let contents_fingerprint = source_fingerprint(&create_source(DirectoryFingerprint::Contents));
+        let path_hash = source_hash(&create_source(DirectoryFingerprint::Contents));
+
+        std::fs::write(base_url.join("src").join("agent.o"), "object").unwrap();
+        assert_eq!(source_fingerprint(&create_source(DirectoryFingerprint::Metadata)), metadata_fingerprint);
+        assert_eq!(source_fingerprint(&create_source(DirectoryFingerprint::Contents)), contents_fingerprint);
+
+        std::fs::write(base_url.join("src").join("agent.c"), "int main() { return 1; }").unwrap();
+        assert_ne!(source_fingerprint(&create_source(DirectoryFingerprint::Metadata)), metadata_fingerprint);
+        assert_ne!(source_fingerprint(&create_source(DirectoryFingerprint::Contents)), contents_fingerprint);
+
+        /* the content only invalidates the build steps, the build directory stays the same */
+        assert_eq!(source_hash(&create_source(DirectoryFingerprint::Contents)), path_hash);
+        assert_eq!(source_hash(&create_source(DirectoryFingerprint::None)), path_hash);
+
+        assert_eq!(create_source(DirectoryFingerprint::None).rerun_if_changed(), vec![base_url.clone()]);
+
+        std::fs::remove_dir_all(&base_url).unwrap();
+    }
+}
@@ -5,17 +5,128 @@ pub use file::*;
 
 mod download;
 pub use download::*;
+
+mod archive;
+pub use archive::*;
+
+mod url;
+pub use url::*;
+
+mod patch;
+pub use patch::*;
+
+mod overlay;
+pub use overlay::*;
 use crate::build::BuildStepError;
-use std::hash::Hasher;
+use std::hash::{Hasher, Hash};
+use std::io::ErrorKind;
 
-#[derive(Debug, Ord, PartialOrd, Eq, PartialEq)]
+/// The reason why a source failed to set up.
+/// Every variant carries the details of the failed operation.
+#[derive(Debug, Clone)]
 pub enum SourceSetupError {
-    SourceDoesNotExists,
-    SourceIsNotReadable,
-    NoSpaceOnDisk,
-    TemporaryPathNotWriteable,
-    AlreadyInitialized,
-    Unknown(String)
+    SourceDoesNotExists(BuildStepError),
+    SourceIsNotReadable(BuildStepError),
+    NoSpaceOnDisk(BuildStepError),
+    /// A local file or directory isn't accessible
+    PermissionDenied(BuildStepError),
+    /// The remote rejected the credentials or none have been available (e.g. a missing SSH key)
+    AuthenticationFailed(BuildStepError),
+    TemporaryPathNotWriteable(BuildStepError),
+    AlreadyInitialized(BuildStepError),
+    /// The remote could not be reached (e.g. DNS failure, refused connection, timeout or HTTP error)
+    NetworkFailure(BuildStepError),
+    /// The requested revision, branch or tag does not exist (locally in offline mode)
+    RevisionNotFound(BuildStepError),
+    /// The downloaded content doesn't match its expected checksum
+    ChecksumMismatch(BuildStepError),
+    /// The checked out revision doesn't match the pinned one, a signature is invalid or a tag has been moved
+    VerificationFailed(BuildStepError),
+    /// The reused checkout contains local modifications which would be overwritten
+    LocalModifications(BuildStepError),
+    Unknown(BuildStepError)
+}
+
+/* error messages of git, curl and ssh which indicate a network failure.
+ * git and curl will be executed with the C locale, so their messages aren't translated.
+ * Messages which are reported for authentication failures and missing repositories as well
+ * (e.g. "Could not read from remote repository") must not be listed here. */
+const NETWORK_FAILURE_MESSAGES: [&str; 9] = [
+    "Could not resolve host",
+    "Could not resolve proxy",
+    "Failed to connect",
+    "Connection refused",
+    "Connection timed out",
+    "Connection reset",
+    "Network is unreachable",
+    "The requested URL returned error: 5",
+    "early EOF"
+];
+
+/* error messages of git, curl and ssh which indicate rejected or missing credentials.
+ * ssh reports them as "Permission denied (<methods>)", which must not be mistaken for a file system error. */
+const AUTHENTICATION_FAILURE_MESSAGES: [&str; 6] = [
+    "Permission denied (",
+    "Authentication failed",
+    "could not read Username",
+    "could not read Password",
+    "The requested URL returned error: 401",
+    "The requested URL returned error: 403"
+];
+
+impl SourceSetupError {
+    pub fn error(&self) -> &BuildStepError {
+        match self {
+            SourceSetupError::SourceDoesNotExists(error) |
+            SourceSetupError::SourceIsNotReadable(error) |
+            SourceSetupError::NoSpaceOnDisk(error) |
+            SourceSetupError::PermissionDenied(error) |
+            SourceSetupError::AuthenticationFailed(error) |
+            SourceSetupError::TemporaryPathNotWriteable(error) |
+            SourceSetupError::AlreadyInitialized(error) |
+            SourceSetupError::NetworkFailure(error) |
+            SourceSetupError::RevisionNotFound(error) |
+            SourceSetupError::ChecksumMismatch(error) |
+            SourceSetupError::VerificationFailed(error) |
+            SourceSetupError::LocalModifications(error) |
+            SourceSetupError::Unknown(error) => error
+        }
+    }
+
+    /// Test if the setup might succeed when being retried later
+    pub fn is_transient(&self) -> bool {
+        matches!(self, SourceSetupError::NetworkFailure(_))
+    }
+}
+
+impl From<BuildStepError> for SourceSetupError {
+    /// Classify a generic error by the kind of its io error.
+    /// Errors of failed commands will be classified by their messages.
+    fn from(error: BuildStepError) -> Self {
+        match error.io_error_kind() {
+            Some(ErrorKind::StorageFull) => return SourceSetupError::NoSpaceOnDisk(error),
+            Some(ErrorKind::PermissionDenied) => return SourceSetupError::PermissionDenied(error),
+            Some(ErrorKind::ConnectionRefused) | Some(ErrorKind::ConnectionReset) | Some(ErrorKind::ConnectionAborted) |
+            Some(ErrorKind::TimedOut) | Some(ErrorKind::NetworkUnreachable) | Some(ErrorKind::HostUnreachable) |
+            Some(ErrorKind::NetworkDown) => return SourceSetupError::NetworkFailure(error),
+            Some(_) => return SourceSetupError::Unknown(error),
+            None => {}
+        }
+
+        let stderr = error.stderr();
+        if stderr.contains("No space left on device") || stderr.contains("There is not enough space on the disk") {
+            SourceSetupError::NoSpaceOnDisk(error)
+        } else if AUTHENTICATION_FAILURE_MESSAGES.iter().any(|message| stderr.contains(message)) {
+            SourceSetupError::AuthenticationFailed(error)
+        } else if stderr.lines().any(|line| line.trim_end().ends_with(": Permission denied")) || stderr.contains("Access is denied") {
+            /* file system errors are reported as "<path>: Permission denied" */
+            SourceSetupError::PermissionDenied(error)
+        } else if NETWORK_FAILURE_MESSAGES.iter().any(|message| stderr.contains(message)) {
+            SourceSetupError::NetworkFailure(error)
+        } else {
+            SourceSetupError::Unknown(error)
+        }
+    }
 }
 
 pub trait BuildSource {
@@ -25,7 +136,73 @@ pub trait BuildSource {
     /// Generate a unique hash which identifies the source and possible changes
     fn hash(&self, target: &mut Box<dyn Hasher>);
 
-    fn setup(&mut self) -> Result<(), BuildStepError>;
+    fn setup(&mut self) -> Result<(), SourceSetupError>;
     fn local_directory(&self) -> &PathBuf;
     fn cleanup(&mut self);
-}
\ No newline at end of file
+
+    /// The exact revision (e.g. a git commit SHA) which has been set up, if the source is versioned
+    fn resolved_revision(&self) -> Option<&str> {
+        None
+    }
+
+    /// Hash the state of the set up source, e.g. the checked out revision or the content of a local directory.
+    /// Unlike `hash`, which identifies the source configuration and names the build directories,
+    /// the fingerprint only decides whether the previously executed build steps are still up to date.
+    ///
+    /// Returns `false` if the state of the source is unknown, all build steps will be executed then.
+    fn fingerprint(&self, target: &mut Box<dyn Hasher>) -> Result<bool, SourceSetupError> {
+        match self.resolved_revision() {
+            Some(revision) => {
+                revision.hash(target);
+                Ok(true)
+            },
+            None => Ok(false)
+        }
+    }
+
+    /// Keep the local checkout apart from the checkouts of other sources with the same origin,
+    /// since a wrapping source will modify it (e.g. by applying patches). Must be called before the setup.
+    /// Sources which work on a directory given by the user (e.g. a local directory) will ignore the key.
+    fn separate_checkout(&mut self, _key: u64) { }
+
+    /// Accept the current modifications of the set up source, which have been made by a wrapping source (e.g. applied patches).
+    /// Sources which refuse to overwrite local modifications will not consider them as such on the next setup.
+    fn accept_local_modifications(&mut self) -> Result<(), SourceSetupError> {
+        Ok(())
+    }
+
+    /// Local paths which should trigger a rerun of the build script when they change
+    fn rerun_if_changed(&self) -> Vec<PathBuf> {
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::source::{BuildSource, SourceSetupError};
+    use std::path::PathBuf;
+    use std::hash::Hasher;
+
+    /// A source which uses an existing directory as it is
+    pub(crate) struct DummyBuildSource {
+        pub(crate) path: PathBuf
+    }
+
+    impl BuildSource for DummyBuildSource {
+        fn name(&self) -> &str {
+            "dummy"
+        }
+
+        fn hash(&self, _state: &mut Box<dyn Hasher>) { }
+
+        fn setup(&mut self) -> Result<(), SourceSetupError> {
+            Ok(())
+        }
+
+        fn local_directory(&self) -> &PathBuf {
+            &self.path
+        }
+
+        fn cleanup(&mut self) { }
+    }
+}
@@ -0,0 +1,386 @@
+use crate::source::{BuildSource, SourceSetupError};
+use std::path::{PathBuf, Path, Component};
+use std::collections::BTreeMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io::Read;
+use crate::util::{create_temporary_path, TemporaryPath};
+use crate::build::BuildStepError;
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum BuildSourceOverlayError {
+    /// The target of a layer is absolute or leaves the merged directory
+    InvalidLayerTarget(PathBuf)
+}
+
+struct OverlayLayer {
+    source: Box<dyn BuildSource>,
+    /// The directory within the merged directory the source will be placed into
+    target: PathBuf
+}
+
+enum OverlayEntry {
+    Directory,
+    File(PathBuf),
+    Symlink(PathBuf)
+}
+
+/// A source which merges several sources into one directory.
+/// Layers are applied in the order they've been added, files of later layers replace the files of earlier ones.
+/// `.git` entries of the layers will not be copied.
+pub struct BuildSourceOverlay {
+    layers: Vec<OverlayLayer>,
+
+    checkout_key: Option<u64>,
+    checkout_folder: Option<PathBuf>,
+    local_folder: Option<TemporaryPath>
+}
+
+impl BuildSourceOverlay {
+    pub fn builder() -> BuildSourceOverlayBuilder {
+        BuildSourceOverlayBuilder::new()
+    }
+
+    /// The name of the merged directory depends on the local directories of the layers and not on their content,
+    /// so it can be updated incrementally. Must be called after all layers have been set up.
+    fn temporary_directory_name(&self) -> String {
+        let mut hash = DefaultHasher::new();
+        for layer in self.layers.iter() {
+            layer.source.local_directory().hash(&mut hash);
+            layer.target.hash(&mut hash);
+        }
+        self.checkout_key.hash(&mut hash);
+        let hash = base64::encode(hash.finish().to_be_bytes()).replace("/", "_");
+        format!("overlay_{}", hash)
+    }
+
+    /// Collect the entries of a directory into the merged entries, replacing already present ones
+    fn collect_entries(directory: &Path, relative_path: &Path, entries: &mut BTreeMap<PathBuf, OverlayEntry>) -> std::io::Result<()> {
+        for entry in std::fs::read_dir(directory)? {
+            let entry = entry?;
+            if entry.file_name() == ".git" {
+                continue;
+            }
+
+            let entry_path = relative_path.join(entry.file_name());
+            let metadata = std::fs::symlink_metadata(entry.path())?;
+
+            /* a file replaces a directory (including its content) and the other way around */
+            let replaces_directory = matches!(entries.get(&entry_path), Some(OverlayEntry::Directory));
+            if replaces_directory && !metadata.is_dir() {
+                let children = entries.range(entry_path.clone()..)
+                    .map(|(path, _)| path)
+                    .take_while(|path| path.starts_with(&entry_path))
+                    .cloned()
+                    .collect::<Vec<_>>();
+                children.iter().for_each(|path| { entries.remove(path); });
+            }
+
+            if metadata.is_dir() {
+                entries.insert(entry_path.clone(), OverlayEntry::Directory);
+                Self::collect_entries(&entry.path(), &entry_path, entries)?;
+            } else if metadata.file_type().is_symlink() && cfg!(unix) {
+                entries.insert(entry_path, OverlayEntry::Symlink(std::fs::read_link(entry.path())?));
+            } else {
+                entries.insert(entry_path, OverlayEntry::File(entry.path()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove all entries of the merged directory which are not part of any layer anymore
+    fn remove_stale_entries(directory: &Path, relative_path: &Path, entries: &BTreeMap<PathBuf, OverlayEntry>) -> std::io::Result<()> {
+        for entry in std::fs::read_dir(directory)? {
+            let entry = entry?;
+            let entry_path = relative_path.join(entry.file_name());
+            let metadata = std::fs::symlink_metadata(entry.path())?;
+
+            match entries.get(&entry_path) {
+                Some(OverlayEntry::Directory) if metadata.is_dir() => Self::remove_stale_entries(&entry.path(), &entry_path, entries)?,
+                Some(OverlayEntry::File(_)) if metadata.is_file() => {},
+                Some(OverlayEntry::Symlink(_)) if metadata.file_type().is_symlink() => {},
+                _ if metadata.is_dir() => std::fs::remove_dir_all(entry.path())?,
+                _ => std::fs::remove_file(entry.path())?
+            }
+        }
+        Ok(())
+    }
+
+    /// Test if the target is an up to date copy of the source.
+    /// Copies get the modification time of their source, so the content only needs to be compared if the source has been touched.
+    fn files_equal(a: &Path, b: &Path) -> std::io::Result<bool> {
+        let (metadata_a, metadata_b) = (std::fs::metadata(a)?, std::fs::metadata(b)?);
+        if metadata_a.len() != metadata_b.len() {
+            return Ok(false);
+        } else if metadata_a.modified()? == metadata_b.modified()? {
+            return Ok(true);
+        }
+
+        let mut content_a = Vec::new();
+        let mut content_b = Vec::new();
+        std::fs::File::open(a)?.read_to_end(&mut content_a)?;
+        std::fs::File::open(b)?.read_to_end(&mut content_b)?;
+        Ok(content_a == content_b)
+    }
+
+    /// Give the copy the modification time of its source
+    fn copy_modification_time(source: &Path, target: &Path) -> std::io::Result<()> {
+        let modified = std::fs::metadata(source)?.modified()?;
+        /* the owner may change the times of read only files on unix, windows requires write access */
+        #[cfg(unix)]
+        let file = std::fs::File::open(target)?;
+        #[cfg(not(unix))]
+        let file = std::fs::OpenOptions::new().write(true).open(target)?;
+        file.set_modified(modified)
+    }
+
+    #[cfg(unix)]
+    fn create_symlink(link_target: &Path, path: &Path) -> std::io::Result<()> {
+        std::os::unix::fs::symlink(link_target, path)
+    }
+
+    /* creating symlinks requires special privileges on windows, so the symlinks will be copied as files instead */
+    #[cfg(not(unix))]
+    fn create_symlink(_link_target: &Path, _path: &Path) -> std::io::Result<()> {
+        Err(std::io::Error::new(std::io::ErrorKind::Other, "symlinks are not supported"))
+    }
+
+    /// Synchronize the merged directory with the layers.
+    /// Files which haven't changed will not be touched, so incremental builds don't rebuild everything.
+    fn merge_layers(&self, target_folder: &Path) -> std::io::Result<()> {
+        let mut entries = BTreeMap::new();
+        for layer in self.layers.iter() {
+            /* create the parent directories of the layer target */
+            for ancestor in layer.target.ancestors().collect::<Vec<_>>().into_iter().rev().skip(1) {
+                if !matches!(entries.get(ancestor), Some(OverlayEntry::Directory)) {
+                    entries.insert(ancestor.to_owned(), OverlayEntry::Directory);
+                }
+            }
+
+            Self::collect_entries(layer.source.local_directory(), &layer.target, &mut entries)?;
+        }
+
+        Self::remove_stale_entries(target_folder, Path::new(""), &entries)?;
+
+        for (path, entry) in entries.iter() {
+            let target = target_folder.join(path);
+            match entry {
+                OverlayEntry::Directory => std::fs::create_dir_all(&target)?,
+                OverlayEntry::File(source) => {
+                    if !target.is_file() || !Self::files_equal(source, &target)? {
+                        std::fs::copy(source, &target)?;
+                        Self::copy_modification_time(source, &target)?;
+                    }
+                },
+                OverlayEntry::Symlink(link_target) => {
+                    if std::fs::read_link(&target).ok().as_ref() != Some(link_target) {
+                        if std::fs::symlink_metadata(&target).is_ok() {
+                            std::fs::remove_file(&target)?;
+                        }
+                        Self::create_symlink(link_target, &target)?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl BuildSource for BuildSourceOverlay {
+    fn name(&self) -> &str {
+        "overlay"
+    }
+
+    fn hash(&self, target: &mut Box<dyn Hasher>) {
+        for (index, layer) in self.layers.iter().enumerate() {
+            index.hash(target);
+            layer.source.name().hash(target);
+            layer.target.hash(target);
+            layer.source.hash(target);
+        }
+    }
+
+    fn fingerprint(&self, target: &mut Box<dyn Hasher>) -> Result<bool, SourceSetupError> {
+        for layer in self.layers.iter() {
+            if !layer.source.fingerprint(target)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn setup(&mut self) -> Result<(), SourceSetupError> {
+        if self.local_folder.is_some() {
+            return Err(SourceSetupError::AlreadyInitialized(BuildStepError::new_simple("the source has already been initialized")));
+        }
+
+        if self.layers.is_empty() {
+            return Err(SourceSetupError::SourceDoesNotExists(BuildStepError::new_simple("the overlay doesn't contain any layers")));
+        }
+
+        for layer in self.layers.iter_mut() {
+            layer.source.setup()?;
+        }
+
+        let target_folder = match create_temporary_path(&self.temporary_directory_name(), self.checkout_folder.as_ref()) {
+            Ok(folder) => {
+                folder.release();
+                folder
+            },
+            Err(err) => return Err(SourceSetupError::TemporaryPathNotWriteable(BuildStepError::new_io("failed to create overlay directory", err)))
+        };
+
+        println!("Merging {} layers into {:?}", self.layers.len(), target_folder.path());
+        self.merge_layers(target_folder.path())
+            .map_err(|err| BuildStepError::new_io("failed to merge overlay layers", err))?;
+
+        self.local_folder = Some(target_folder);
+        Ok(())
+    }
+
+    fn local_directory(&self) -> &PathBuf {
+        self.local_folder.as_ref().expect("expected a path")
+            .path()
+    }
+
+    fn separate_checkout(&mut self, key: u64) {
+        /* the layers will only be copied, so they can still be shared */
+        self.checkout_key = Some(key);
+    }
+
+    fn cleanup(&mut self) {
+        for layer in self.layers.iter_mut() {
+            layer.source.cleanup();
+        }
+        self.local_folder = None;
+    }
+
+    fn rerun_if_changed(&self) -> Vec<PathBuf> {
+        self.layers.iter()
+            .flat_map(|layer| layer.source.rerun_if_changed())
+            .collect()
+    }
+}
+
+pub struct BuildSourceOverlayBuilder {
+    inner: BuildSourceOverlay
+}
+
+impl BuildSourceOverlayBuilder {
+    fn new() -> Self {
+        BuildSourceOverlayBuilder {
+            inner: BuildSourceOverlay {
+                layers: Vec::new(),
+
+                checkout_key: None,
+                checkout_folder: None,
+                local_folder: None
+            }
+        }
+    }
+
+    /// Add a layer on top of all previous layers
+    pub fn layer(self, source: Box<dyn BuildSource>) -> Self {
+        self.layer_into(source, PathBuf::new())
+    }
+
+    /// Add a layer on top of all previous layers, placing its content within the given subdirectory (e.g. `subprojects`).
+    /// The subdirectory must be a relative path within the merged directory.
+    pub fn layer_into(mut self, source: Box<dyn BuildSource>, target: PathBuf) -> Self {
+        self.inner.layers.push(OverlayLayer{ source, target });
+        self
+    }
+
+    pub fn checkout_folder(mut self, path: Option<PathBuf>) -> Self {
+        self.inner.checkout_folder = path;
+        self
+    }
+
+    pub fn build(self) -> Result<BuildSourceOverlay, BuildSourceOverlayError> {
+        let invalid_target = self.inner.layers.iter()
+            .map(|layer| &layer.target)
+            .find(|target| !target.components().all(|component| matches!(component, Component::Normal(_) | Component::CurDir)));
+
+        match invalid_target {
+            Some(target) => Err(BuildSourceOverlayError::InvalidLayerTarget(target.clone())),
+            None => Ok(self.inner)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::source::{BuildSourceOverlay, BuildSourceOverlayError, BuildSource, BuildSourceDirectory};
+    use std::path::{PathBuf, Path};
+
+    fn directory_source(path: &Path) -> Box<dyn BuildSource> {
+        Box::new(BuildSourceDirectory::new(path.to_owned()).expect("failed to create directory source"))
+    }
+
+    #[test]
+    fn test_overlay() {
+        let base_url = std::env::temp_dir().join("__test_overlay");
+        let _ = std::fs::remove_dir_all(&base_url);
+
+        let upstream = base_url.join("upstream");
+        std::fs::create_dir_all(upstream.join("src")).unwrap();
+        std::fs::create_dir_all(upstream.join(".git")).unwrap();
+        std::fs::write(upstream.join("meson.build"), "project('upstream', 'c')").unwrap();
+        std::fs::write(upstream.join("src").join("agent.c"), "int main() {}").unwrap();
+        std::fs::write(upstream.join("obsolete.txt"), "obsolete").unwrap();
+
+        let replacement = base_url.join("replacement");
+        std::fs::create_dir_all(&replacement).unwrap();
+        std::fs::write(replacement.join("meson.build"), "project('replacement', 'c')").unwrap();
+
+        let wraps = base_url.join("wraps");
+        std::fs::create_dir_all(&wraps).unwrap();
+        std::fs::write(wraps.join("glib.wrap"), "[wrap-git]").unwrap();
+
+        let setup_overlay = || {
+            let mut source = BuildSourceOverlay::builder()
+                .layer(directory_source(&upstream))
+                .layer(directory_source(&replacement))
+                .layer_into(directory_source(&wraps), PathBuf::from("subprojects"))
+                .checkout_folder(Some(base_url.join("checkout")))
+                .build()
+                .expect("failed to create overlay");
+            source.setup().expect("failed to setup overlay");
+            source
+        };
+
+        let source = setup_overlay();
+        let merged = source.local_directory().clone();
+        assert_eq!(std::fs::read_to_string(merged.join("meson.build")).unwrap(), "project('replacement', 'c')");
+        assert!(merged.join("src").join("agent.c").is_file());
+        assert!(merged.join("subprojects").join("glib.wrap").is_file());
+        assert!(!merged.join(".git").exists());
+        assert_eq!(source.rerun_if_changed(), vec![upstream.clone(), replacement.clone(), wraps.clone()]);
+
+        /* unchanged files must not be touched */
+        let modified = std::fs::metadata(merged.join("src").join("agent.c")).unwrap().modified().unwrap();
+        std::fs::remove_file(upstream.join("obsolete.txt")).unwrap();
+        std::fs::remove_file(replacement.join("meson.build")).unwrap();
+
+        let source = setup_overlay();
+        assert_eq!(source.local_directory(), &merged);
+        assert!(!merged.join("obsolete.txt").exists());
+        assert_eq!(std::fs::read_to_string(merged.join("meson.build")).unwrap(), "project('upstream', 'c')");
+        assert_eq!(std::fs::metadata(merged.join("src").join("agent.c")).unwrap().modified().unwrap(), modified);
+
+        /* changes which don't alter the file size must be detected as well */
+        std::fs::write(upstream.join("src").join("agent.c"), "int main(){ }").unwrap();
+        let source = setup_overlay();
+        assert_eq!(std::fs::read_to_string(source.local_directory().join("src").join("agent.c")).unwrap(), "int main(){ }");
+
+        for target in [base_url.join("escape"), PathBuf::from("../escape"), PathBuf::from("subprojects/../../escape")] {
+            let result = BuildSourceOverlay::builder()
+                .layer_into(directory_source(&wraps), target.clone())
+                .build();
+            assert_eq!(result.err(), Some(BuildSourceOverlayError::InvalidLayerTarget(target)));
+        }
+
+        std::fs::remove_dir_all(&base_url).unwrap();
+    }
+}
@@ -0,0 +1,616 @@
+use crate::source::{BuildSource, SourceSetupError};
+use std::path::{PathBuf, Path};
+use std::process::Command;
+use crate::util::execute_build_command;
+use std::hash::{Hash, Hasher};
+use std::collections::hash_map::DefaultHasher;
+use crate::build::BuildStepError;
+
+#[derive(Ord, PartialOrd, Eq, PartialEq, Debug, Copy, Clone)]
+pub enum PatchMethod {
+    /// Apply the patches with `git apply`
+    GitApply,
+    /// Apply the patches with the built in unified diff applier
+    Builtin
+}
+
+/// A source which applies an ordered list of patches on top of another source.
+/// Patches which have already been applied (e.g. within a reused local directory) will be skipped.
+pub struct BuildSourcePatched {
+    source: Box<dyn BuildSource>,
+    patches: Vec<PathBuf>,
+    method: PatchMethod,
+    strip_components: usize,
+    checkout_key: Option<u64>
+}
+
+impl BuildSourcePatched {
+    pub fn builder(source: Box<dyn BuildSource>) -> BuildSourcePatchedBuilder {
+        BuildSourcePatchedBuilder::new(source)
+    }
+
+    /// Hash the patch set, a missing patch will be hashed by its path since the setup will fail anyways
+    fn hash_patches<H: Hasher>(&self, target: &mut H) {
+        self.strip_components.hash(target);
+        for patch in self.patches.iter() {
+            match std::fs::read(patch) {
+                Ok(content) => content.hash(target),
+                Err(_) => patch.hash(target)
+            }
+        }
+    }
+
+    fn patch_name(patch: &Path) -> String {
+        patch.file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| patch.to_string_lossy().into_owned())
+    }
+
+    fn git_apply_command(&self, patch: &Path, reverse: bool) -> Command {
+        let source_directory = self.source.local_directory();
+
+        let mut command = Command::new("git");
+        command.arg("apply")
+               .arg(format!("-p{}", self.strip_components))
+               .arg("--whitespace=nowarn");
+        if reverse {
+            command.arg("--reverse").arg("--check");
+        }
+        command.arg(patch)
+               .current_dir(source_directory);
+
+        /* don't let git pick up a repository the source directory is contained in (e.g. the crates repository) */
+        if let Some(parent) = source_directory.parent() {
+            command.env("GIT_CEILING_DIRECTORIES", parent);
+        }
+        command
+    }
+
+    fn apply_git(&self, patch: &Path) -> Result<(), BuildStepError> {
+        let patch_name = Self::patch_name(patch);
+        if self.git_apply_command(patch, true).output().map(|output| output.status.success()).unwrap_or(false) {
+            println!("Patch {} has already been applied", &patch_name);
+            return Ok(());
+        }
+
+        let mut command = self.git_apply_command(patch, false);
+        execute_build_command(&mut command, &format!("failed to apply patch {}", &patch_name))
+            .map_err(|error| {
+                /* git reports the failed hunk as "error: patch failed: <file>:<line>" */
+                let hunk = error.stderr().lines()
+                    .filter_map(|line| line.split("patch failed: ").nth(1))
+                    .next()
+                    .map(|location| location.to_owned());
+
+                match hunk {
+                    Some(hunk) => BuildStepError::new(format!("failed to apply patch {}: hunk at {} does not apply", &patch_name, hunk), error.stdout().to_owned(), error.stderr().to_owned()),
+                    None => error
+                }
+            })?;
+        Ok(())
+    }
+
+    fn apply_builtin(&self, patch: &Path) -> Result<(), BuildStepError> {
+        let patch_name = Self::patch_name(patch);
+        let content = std::fs::read_to_string(patch)
+            .map_err(|err| BuildStepError::new_io(format!("failed to read patch {}", &patch_name), err))?;
+
+        let files = parse_unified_diff(&content, self.strip_components)
+            .map_err(|error| BuildStepError::new_simple(format!("failed to parse patch {}: {}", &patch_name, error)))?;
+
+        let source_directory = self.source.local_directory();
+        let read_target = |path: &Option<String>| -> Result<String, BuildStepError> {
+            match path {
+                Some(path) => std::fs::read_to_string(source_directory.join(path))
+                    .map_err(|err| BuildStepError::new_io(format!("failed to apply patch {}: failed to read {}", &patch_name, path), err)),
+                None => Ok(String::new())
+            }
+        };
+
+        /* check if the patch has already been applied by reverting it */
+        let already_applied = files.iter().all(|file| {
+            if file.new_path.is_none() {
+                return file.old_path.as_ref().map(|path| !source_directory.join(path).exists()).unwrap_or(true);
+            }
+            match read_target(&file.new_path) {
+                Ok(content) => apply_hunks(&content, &file.hunks, true).is_ok(),
+                Err(_) => false
+            }
+        });
+
+        if already_applied {
+            println!("Patch {} has already been applied", &patch_name);
+            return Ok(());
+        }
+
+        /* apply all files in memory first, so a failing hunk doesn't leave a half applied patch behind */
+        let mut results = Vec::with_capacity(files.len());
+        for file in files.iter() {
+            let content = read_target(&file.old_path)?;
+            let content = apply_hunks(&content, &file.hunks, false)
+                .map_err(|hunk| {
+                    let file_name = file.new_path.as_ref().or(file.old_path.as_ref()).cloned().unwrap_or_default();
+                    BuildStepError::new_simple(format!("failed to apply patch {}: hunk #{} ({}) of {} does not apply", &patch_name, hunk + 1, file.hunks[hunk].header, file_name))
+                })?;
+            results.push((file, content));
+        }
+
+        for (file, content) in results {
+            let io_error = |err| BuildStepError::new_io(format!("failed to apply patch {}", &patch_name), err);
+            match &file.new_path {
+                Some(path) => {
+                    let path = source_directory.join(path);
+                    if let Some(parent) = path.parent() {
+                        std::fs::create_dir_all(parent).map_err(io_error)?;
+                    }
+                    std::fs::write(path, content).map_err(io_error)?;
+                },
+                None => {
+                    if let Some(path) = &file.old_path {
+                        std::fs::remove_file(source_directory.join(path)).map_err(io_error)?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl BuildSource for BuildSourcePatched {
+    fn name(&self) -> &str {
+        "patched source"
+    }
+
+    fn hash(&self, target: &mut Box<dyn Hasher>) {
+        self.source.hash(target);
+        self.hash_patches(target);
+    }
+
+    fn setup(&mut self) -> Result<(), SourceSetupError> {
+        /* the patches will be applied in place, so other sources with the same origin must not share the checkout */
+        let mut checkout_key = DefaultHasher::new();
+        self.checkout_key.hash(&mut checkout_key);
+        self.hash_patches(&mut checkout_key);
+        self.source.separate_checkout(checkout_key.finish());
+
+        self.source.setup()?;
+
+        for patch in self.patches.iter() {
+            println!("Applying patch {:?}", patch);
+            if !patch.is_file() {
+                return Err(SourceSetupError::SourceDoesNotExists(BuildStepError::new_simple(format!("patch {:?} does not exist", patch))));
+            }
+
+            match self.method {
+                PatchMethod::GitApply => self.apply_git(patch)?,
+                PatchMethod::Builtin => self.apply_builtin(patch)?
+            }
+        }
+
+        self.source.accept_local_modifications()
+    }
+
+    fn local_directory(&self) -> &PathBuf {
+        self.source.local_directory()
+    }
+
+    fn resolved_revision(&self) -> Option<&str> {
+        self.source.resolved_revision()
+    }
+
+    fn fingerprint(&self, target: &mut Box<dyn Hasher>) -> Result<bool, SourceSetupError> {
+        self.source.fingerprint(target)
+    }
+
+    fn separate_checkout(&mut self, key: u64) {
+        /* our own checkout key will be combined with the key of the wrapping source on setup */
+        self.checkout_key = Some(key);
+    }
+
+    fn accept_local_modifications(&mut self) -> Result<(), SourceSetupError> {
+        self.source.accept_local_modifications()
+    }
+
+    fn cleanup(&mut self) {
+        self.source.cleanup();
+    }
+
+    fn rerun_if_changed(&self) -> Vec<PathBuf> {
+        let mut paths = self.source.rerun_if_changed();
+        paths.extend(self.patches.iter().cloned());
+        paths
+    }
+}
+
+pub struct BuildSourcePatchedBuilder {
+    inner: BuildSourcePatched
+}
+
+impl BuildSourcePatchedBuilder {
+    fn new(source: Box<dyn BuildSource>) -> Self {
+        BuildSourcePatchedBuilder {
+            inner: BuildSourcePatched {
+                source,
+                patches: Vec::new(),
+                method: PatchMethod::GitApply,
+                strip_components: 1,
+                checkout_key: None
+            }
+        }
+    }
+
+    /// Append a patch file. Patches will be applied in the order they've been added.
+    pub fn patch(mut self, patch: PathBuf) -> Self {
+        self.inner.patches.push(patch);
+        self
+    }
+
+    pub fn method(mut self, method: PatchMethod) -> Self {
+        self.inner.method = method;
+        self
+    }
+
+    /// The number of leading path components which will be removed from the file names within the patches (`-p<n>`, default 1)
+    pub fn strip_components(mut self, count: usize) -> Self {
+        self.inner.strip_components = count;
+        self
+    }
+
+    pub fn build(self) -> BuildSourcePatched {
+        self.inner
+    }
+}
+
+/// The lines of a hunk including their line terminators.
+/// A line without a terminator has been marked with `\ No newline at end of file`.
+#[derive(Debug)]
+struct PatchHunk {
+    header: String,
+    old_start: usize,
+    old_lines: Vec<String>,
+    new_lines: Vec<String>
+}
+
+#[derive(Debug)]
+struct PatchFile {
+    /// `None` if the file will be created
+    old_path: Option<String>,
+    /// `None` if the file will be deleted
+    new_path: Option<String>,
+    hunks: Vec<PatchHunk>
+}
+
+fn parse_patch_path(line: &str, strip_components: usize) -> Result<Option<String>, String> {
+    /* cut of the optional timestamp */
+    let path = line[4..].split('\t').next().unwrap_or("").trim_end();
+    if path == "/dev/null" {
+        return Ok(None);
+    }
+
+    let components = path.split('/').collect::<Vec<_>>();
+    if components.len() <= strip_components {
+        return Err(format!("can not strip {} components of {}", strip_components, path));
+    }
+    Ok(Some(components[strip_components..].join("/")))
+}
+
+fn parse_hunk_range(range: &str) -> Result<(usize, usize), String> {
+    let mut parts = range.splitn(2, ',');
+    let start = parts.next().unwrap_or("").parse::<usize>().map_err(|_| format!("invalid hunk range {}", range))?;
+    let count = match parts.next() {
+        Some(count) => count.parse::<usize>().map_err(|_| format!("invalid hunk range {}", range))?,
+        None => 1
+    };
+    Ok((start, count))
+}
+
+fn parse_unified_diff(content: &str, strip_components: usize) -> Result<Vec<PatchFile>, String> {
+    /* keep the line terminators, patches for files with CRLF line endings contain them as well */
+    let lines = content.split_inclusive('\n').collect::<Vec<_>>();
+    let mut files = Vec::new();
+
+    let mut index = 0;
+    while index < lines.len() {
+        if !lines[index].starts_with("--- ") || index + 1 >= lines.len() || !lines[index + 1].starts_with("+++ ") {
+            if lines[index].starts_with("GIT binary patch") {
+                return Err("binary patches are not supported".to_owned());
+            }
+            index += 1;
+            continue;
+        }
+
+        let old_path = parse_patch_path(lines[index], strip_components)?;
+        let new_path = parse_patch_path(lines[index + 1], strip_components)?;
+        index += 2;
+
+        let mut hunks = Vec::new();
+        while index < lines.len() && lines[index].starts_with("@@ ") {
+            let header = lines[index].trim_end_matches(&['\r', '\n'][..]);
+            let ranges = header[3..].split(" @@").next().unwrap_or("");
+            let mut ranges = ranges.split(' ');
+            let old_range = ranges.next().and_then(|range| range.strip_prefix('-')).ok_or_else(|| format!("invalid hunk header {}", header))?;
+            let new_range = ranges.next().and_then(|range| range.strip_prefix('+')).ok_or_else(|| format!("invalid hunk header {}", header))?;
+            let (old_start, mut old_remaining) = parse_hunk_range(old_range)?;
+            let (_, mut new_remaining) = parse_hunk_range(new_range)?;
+            index += 1;
+
+            let mut hunk = PatchHunk{
+                header: header.to_owned(),
+                old_start,
+                old_lines: Vec::new(),
+                new_lines: Vec::new()
+            };
+
+            let mut last_operation = ' ';
+            while index < lines.len() && (old_remaining > 0 || new_remaining > 0 || lines[index].starts_with('\\')) {
+                let line = lines[index];
+                let (operation, text) = match line.chars().next() {
+                    /* some editors strip the trailing space of empty context lines */
+                    Some('\r') | Some('\n') => (' ', line),
+                    Some(operation) => (operation, &line[1..]),
+                    None => (' ', "")
+                };
+                /* the last line of the patch file may lack its newline */
+                let text = if text.ends_with('\n') { text.to_owned() } else { format!("{}\n", text) };
+
+                match operation {
+                    ' ' if old_remaining > 0 && new_remaining > 0 => {
+                        hunk.old_lines.push(text.clone());
+                        hunk.new_lines.push(text);
+                        old_remaining -= 1;
+                        new_remaining -= 1;
+                    },
+                    '-' if old_remaining > 0 => {
+                        hunk.old_lines.push(text);
+                        old_remaining -= 1;
+                    },
+                    '+' if new_remaining > 0 => {
+                        hunk.new_lines.push(text);
+                        new_remaining -= 1;
+                    },
+                    '\\' => {
+                        /* \ No newline at end of file: the previous line has no terminator */
+                        let strip_newline = |lines: &mut Vec<String>| {
+                            if let Some(line) = lines.last_mut() {
+                                line.pop();
+                            }
+                        };
+                        match last_operation {
+                            '-' => strip_newline(&mut hunk.old_lines),
+                            '+' => strip_newline(&mut hunk.new_lines),
+                            _ => {
+                                strip_newline(&mut hunk.old_lines);
+                                strip_newline(&mut hunk.new_lines);
+                            }
+                        }
+                    },
+                    _ => return Err(format!("unexpected line \"{}\" within hunk {}", line.trim_end(), header))
+                }
+
+                if operation != '\\' {
+                    last_operation = operation;
+                }
+                index += 1;
+            }
+
+            if old_remaining > 0 || new_remaining > 0 {
+                return Err(format!("hunk {} is truncated", header));
+            }
+            hunks.push(hunk);
+        }
+
+        if hunks.is_empty() {
+            return Err(format!("missing hunks for {}", new_path.as_ref().or(old_path.as_ref()).cloned().unwrap_or_default()));
+        }
+        files.push(PatchFile{ old_path, new_path, hunks });
+    }
+
+    if files.is_empty() {
+        return Err("the patch does not contain any changes".to_owned());
+    }
+    Ok(files)
+}
+
+/// Apply the hunks to the given content.
+/// The lines are compared including their terminators, so line endings and a missing newline at the end of the file are preserved.
+/// Returns the index of the failed hunk on failure.
+fn apply_hunks(content: &str, hunks: &[PatchHunk], reverse: bool) -> Result<String, usize> {
+    let mut lines = content.split_inclusive('\n').map(|line| line.to_owned()).collect::<Vec<_>>();
+
+    /* the offset between the expected and the actual position of the previous hunk */
+    let mut offset: isize = 0;
+    let mut minimum_position = 0;
+    for (index, hunk) in hunks.iter().enumerate() {
+        let (expected, replacement) = if reverse {
+            (&hunk.new_lines, &hunk.old_lines)
+        } else {
+            (&hunk.old_lines, &hunk.new_lines)
+        };
+
+        /* for hunks without old lines the start is the line after which the new lines will be inserted */
+        let start = if hunk.old_lines.is_empty() { hunk.old_start } else { hunk.old_start.saturating_sub(1) };
+        let start = (start as isize + offset).max(0) as usize;
+
+        let matches_at = |position: usize| {
+            position >= minimum_position &&
+                position + expected.len() <= lines.len() &&
+                lines[position..position + expected.len()] == expected[..]
+        };
+
+        let position = (0..=lines.len())
+            .flat_map(|distance| vec![start.checked_add(distance), start.checked_sub(distance)])
+            .flatten()
+            .find(|position| matches_at(*position))
+            .ok_or(index)?;
+
+        let end = position + expected.len();
+        lines.splice(position..end, replacement.iter().cloned());
+
+        offset += replacement.len() as isize - expected.len() as isize;
+        minimum_position = position + replacement.len();
+    }
+
+    Ok(lines.concat())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::source::{BuildSourcePatched, BuildSource, BuildSourceArchive, PatchMethod};
+    use crate::source::test::DummyBuildSource;
+    use crate::source::patch::{parse_unified_diff, apply_hunks};
+    use std::path::PathBuf;
+
+    const SOURCE_FILE: &str = "line 1\nline 2\nline 3\nline 4\nline 5\nline 6\nline 7\nline 8\n";
+    const PATCH: &str = "diff --git a/src/agent.c b/src/agent.c
+index 1111111..2222222 100644
+--- a/src/agent.c
++++ b/src/agent.c
+@@ -1,3 +1,3 @@
+ line 1
+-line 2
++line two
+ line 3
+@@ -6,3 +6,4 @@ some function
+ line 6
+ line 7
++line 7.5
+ line 8
+--- /dev/null
++++ b/src/new.c
+@@ -0,0 +1,2 @@
++new 1
++new 2
+";
+
+    #[test]
+    fn test_apply_hunks() {
+        let files = parse_unified_diff(PATCH, 1).expect("failed to parse patch");
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].old_path.as_deref(), Some("src/agent.c"));
+        assert_eq!(files[1].old_path, None);
+        assert_eq!(files[1].new_path.as_deref(), Some("src/new.c"));
+
+        let patched = apply_hunks(SOURCE_FILE, &files[0].hunks, false).expect("failed to apply hunks");
+        assert_eq!(patched, "line 1\nline two\nline 3\nline 4\nline 5\nline 6\nline 7\nline 7.5\nline 8\n");
+        assert_eq!(apply_hunks(&patched, &files[0].hunks, true).expect("failed to revert hunks"), SOURCE_FILE);
+        assert_eq!(apply_hunks("", &files[1].hunks, false).expect("failed to apply hunks"), "new 1\nnew 2\n");
+
+        /* hunks should be found if the lines have been moved */
+        let moved = format!("line 0\n{}", SOURCE_FILE);
+        assert!(apply_hunks(&moved, &files[0].hunks, false).is_ok());
+
+        let modified = SOURCE_FILE.replace("line 7", "line seven");
+        assert_eq!(apply_hunks(&modified, &files[0].hunks, false).err(), Some(1));
+    }
+
+    #[test]
+    fn test_apply_hunks_line_endings() {
+        /* lines outside of the hunks keep their line endings */
+        let files = parse_unified_diff(PATCH, 1).expect("failed to parse patch");
+        let mixed = SOURCE_FILE.replace("line 4\n", "line 4\r\n");
+        let patched = apply_hunks(&mixed, &files[0].hunks, false).expect("failed to apply hunks");
+        assert_eq!(patched, "line 1\nline two\nline 3\nline 4\r\nline 5\nline 6\nline 7\nline 7.5\nline 8\n");
+
+        /* patches for files with CRLF line endings contain the CRLF line endings as well */
+        let files = parse_unified_diff(&PATCH.replace('\n', "\r\n"), 1).expect("failed to parse CRLF patch");
+        let patched = apply_hunks(&SOURCE_FILE.replace('\n', "\r\n"), &files[0].hunks, false).expect("failed to apply CRLF hunks");
+        assert_eq!(patched, "line 1\r\nline two\r\nline 3\r\nline 4\r\nline 5\r\nline 6\r\nline 7\r\nline 7.5\r\nline 8\r\n");
+        assert!(apply_hunks(SOURCE_FILE, &files[0].hunks, false).is_err());
+
+        let patch = "--- a/file.txt\n+++ b/file.txt\n@@ -1,2 +1,2 @@\n line 1\n-line 2\n\\ No newline at end of file\n+line two\n";
+        let files = parse_unified_diff(patch, 1).expect("failed to parse patch");
+        assert_eq!(apply_hunks("line 1\nline 2", &files[0].hunks, false).expect("failed to apply hunks"), "line 1\nline two\n");
+        assert_eq!(apply_hunks("line 1\nline two\n", &files[0].hunks, true).expect("failed to revert hunks"), "line 1\nline 2");
+        assert!(apply_hunks("line 1\nline 2\n", &files[0].hunks, false).is_err());
+    }
+
+    fn setup_source(name: &str, method: PatchMethod) -> (PathBuf, PathBuf) {
+        let base_url = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_dir_all(&base_url);
+        std::fs::create_dir_all(base_url.join("source").join("src")).unwrap();
+        std::fs::write(base_url.join("source").join("src").join("agent.c"), SOURCE_FILE).unwrap();
+        std::fs::write(base_url.join("fix.patch"), PATCH).unwrap();
+
+        let mut source = BuildSourcePatched::builder(Box::new(DummyBuildSource{ path: base_url.join("source") }))
+            .patch(base_url.join("fix.patch"))
+            .method(method)
+            .build();
+
+        source.setup().expect("failed to apply patch");
+        assert!(std::fs::read_to_string(base_url.join("source/src/agent.c")).unwrap().contains("line 7.5"));
+        assert_eq!(std::fs::read_to_string(base_url.join("source/src/new.c")).unwrap(), "new 1\nnew 2\n");
+
+        /* applying the patches again must not fail */
+        source.setup().expect("failed to apply patch again");
+        (base_url.clone(), base_url.join("source"))
+    }
+
+    #[test]
+    fn test_builtin_patch() {
+        let (base_url, source_path) = setup_source("__test_builtin_patch", PatchMethod::Builtin);
+
+        std::fs::write(source_path.join("src").join("agent.c"), SOURCE_FILE.replace("line 7", "line seven")).unwrap();
+        std::fs::remove_file(source_path.join("src").join("new.c")).unwrap();
+        let mut source = BuildSourcePatched::builder(Box::new(DummyBuildSource{ path: source_path }))
+            .patch(base_url.join("fix.patch"))
+            .method(PatchMethod::Builtin)
+            .build();
+
+        let error = source.setup().expect_err("patch should not apply");
+        assert!(format!("{:?}", error).contains("failed to apply patch fix.patch: hunk #2 (@@ -6,3 +6,4 @@ some function) of src/agent.c"));
+
+        std::fs::remove_dir_all(&base_url).unwrap();
+    }
+
+    #[test]
+    fn test_git_apply_patch() {
+        let (base_url, source_path) = setup_source("__test_git_apply_patch", PatchMethod::GitApply);
+
+        std::fs::write(source_path.join("src").join("agent.c"), SOURCE_FILE.replace("line 7", "line seven")).unwrap();
+        std::fs::remove_file(source_path.join("src").join("new.c")).unwrap();
+        let mut source = BuildSourcePatched::builder(Box::new(DummyBuildSource{ path: source_path }))
+            .patch(base_url.join("fix.patch"))
+            .build();
+
+        let error = source.setup().expect_err("patch should not apply");
+        assert!(format!("{:?}", error).contains("failed to apply patch fix.patch: hunk at src/agent.c:6"));
+
+        std::fs::remove_dir_all(&base_url).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_patched_archive() {
+        let base_url = std::env::temp_dir().join("__test_patched_archive");
+        let _ = std::fs::remove_dir_all(&base_url);
+        std::fs::create_dir_all(base_url.join("content").join("src")).unwrap();
+        std::fs::write(base_url.join("content").join("src").join("agent.c"), SOURCE_FILE).unwrap();
+        std::fs::write(base_url.join("fix.patch"), PATCH).unwrap();
+
+        let archive = base_url.join("libtest-1.0.tar.gz");
+        let status = std::process::Command::new("tar").arg("-czf").arg(&archive).arg("-C").arg(base_url.join("content")).arg("src").status().unwrap();
+        assert!(status.success());
+
+        let create_archive = || {
+            Box::new(BuildSourceArchive::builder(archive.clone())
+                .checkout_folder(Some(base_url.join("checkout")))
+                .build())
+        };
+
+        let mut patched = BuildSourcePatched::builder(create_archive())
+            .patch(base_url.join("fix.patch"))
+            .build();
+        patched.setup().expect("failed to setup patched archive");
+        assert!(patched.local_directory().join("src").join("new.c").is_file());
+
+        /* the unpatched archive must not reuse the patched checkout */
+        let mut unpatched = create_archive();
+        unpatched.setup().expect("failed to setup archive");
+        assert_ne!(unpatched.local_directory(), patched.local_directory());
+        assert_eq!(std::fs::read_to_string(unpatched.local_directory().join("src").join("agent.c")).unwrap(), SOURCE_FILE);
+        assert!(!unpatched.local_directory().join("src").join("new.c").exists());
+
+        std::fs::remove_dir_all(&base_url).unwrap();
+    }
+}
@@ -0,0 +1,327 @@
+use crate::source::{BuildSource, BuildSourceArchive, SourceSetupError};
+use std::path::{PathBuf, Path};
+use std::process::Command;
+use std::io::Read;
+use crate::util::{create_temporary_path, execute_build_command, offline_mode_enabled};
+use std::hash::{Hash, Hasher};
+use crate::build::BuildStepError;
+use sha2::{Sha256, Digest};
+
+/// Calculate the hex encoded SHA-256 of a file
+fn sha256_file(path: &Path) -> std::io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = [0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+
+    Ok(hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// Classify a failed curl invocation by its exit code
+fn curl_error(error: BuildStepError) -> SourceSetupError {
+    match error.exit_code() {
+        /* couldn't resolve the proxy or host, failed to connect, timeout, SSL connect error, empty reply or a failed transfer */
+        Some(5) | Some(6) | Some(7) | Some(28) | Some(35) | Some(52) | Some(55) | Some(56) => SourceSetupError::NetworkFailure(error),
+        _ => error.into()
+    }
+}
+
+/// A source which will be downloaded from an url and unpacked like a `BuildSourceArchive`.
+/// The downloaded archive must match the given SHA-256 and will be cached for later builds.
+pub struct BuildSourceUrl {
+    url: String,
+    sha256: String,
+    strip_leading_component: bool,
+
+    cache_folder: Option<PathBuf>,
+    checkout_key: Option<u64>,
+    checkout_folder: Option<PathBuf>,
+    offline: Option<bool>,
+    archive: Option<BuildSourceArchive>
+}
+
+impl BuildSourceUrl {
+    pub fn builder(url: String, sha256: String) -> BuildSourceUrlBuilder {
+        BuildSourceUrlBuilder::new(url, sha256)
+    }
+
+    fn archive_file_name(&self) -> String {
+        let path = self.url.split(['?', '#']).next().unwrap_or("");
+        let file_name = path.rsplit('/').next().filter(|name| !name.is_empty()).unwrap_or("archive");
+        format!("{}_{}", &self.sha256[..16.min(self.sha256.len())], file_name)
+    }
+
+    fn cache_folder(&self) -> Result<PathBuf, SourceSetupError> {
+        if let Some(folder) = &self.cache_folder {
+            std::fs::create_dir_all(folder)
+                .map_err(|err| BuildStepError::new_io("failed to create download cache directory", err))?;
+            Ok(folder.clone())
+        } else {
+            let folder = create_temporary_path("downloads", None)
+                .map_err(|err| BuildStepError::new_io("failed to create download cache directory", err))?;
+            folder.release();
+            Ok(folder.path().clone())
+        }
+    }
+
+    /// Get the cached archive or download it if it hasn't been cached yet.
+    fn cached_archive(&self) -> Result<PathBuf, SourceSetupError> {
+        let archive_path = self.cache_folder()?.join(self.archive_file_name());
+        if archive_path.is_file() {
+            match sha256_file(&archive_path) {
+                Ok(checksum) if checksum == self.sha256 => {
+                    println!("Using cached download {:?}", &archive_path);
+                    return Ok(archive_path);
+                },
+                _ => {
+                    println!("Cached download {:?} is invalid. Downloading it again.", &archive_path);
+                    std::fs::remove_file(&archive_path)
+                        .map_err(|err| BuildStepError::new_io("failed to remove invalid cached download", err))?;
+                }
+            }
+        }
+
+        if self.offline.unwrap_or_else(offline_mode_enabled) {
+            return Err(SourceSetupError::SourceDoesNotExists(BuildStepError::new_simple(format!("offline mode: {} has not been downloaded to {:?} yet", &self.url, &archive_path))));
+        }
+
+        println!("Downloading {}", &self.url);
+        let mut download_path = archive_path.clone().into_os_string();
+        download_path.push(".part");
+        let download_path = PathBuf::from(download_path);
+
+        let mut command = Command::new("curl");
+        command.env("LC_ALL", "C")
+            .arg("--fail")
+            .arg("--silent")
+            .arg("--show-error")
+            .arg("--location")
+            .arg("--output")
+            .arg(&download_path)
+            .arg(&self.url);
+        execute_build_command(&mut command, format!("failed to download {}", &self.url).as_str())
+            .map_err(curl_error)?;
+
+        let checksum = sha256_file(&download_path)
+            .map_err(|err| BuildStepError::new_io("failed to calculate download checksum", err))?;
+
+        if checksum != self.sha256 {
+            let _ = std::fs::remove_file(&download_path);
+            return Err(SourceSetupError::ChecksumMismatch(BuildStepError::new_simple(format!("checksum mismatch for {}: expected {}, but received {}", &self.url, &self.sha256, checksum))));
+        }
+
+        std::fs::rename(&download_path, &archive_path)
+            .map_err(|err| BuildStepError::new_io("failed to move download into the cache", err))?;
+
+        Ok(archive_path)
+    }
+}
+
+impl BuildSource for BuildSourceUrl {
+    fn name(&self) -> &str {
+        "remote archive"
+    }
+
+    fn hash(&self, target: &mut Box<dyn Hasher>) {
+        /* the content is pinned by its checksum, so a changed mirror url doesn't require a rebuild */
+        self.sha256.hash(target);
+        self.strip_leading_component.hash(target);
+    }
+
+    fn setup(&mut self) -> Result<(), SourceSetupError> {
+        if self.archive.is_some() {
+            return Err(SourceSetupError::AlreadyInitialized(BuildStepError::new_simple("the source has already been initialized")));
+        }
+
+        if self.sha256.len() != 64 || !self.sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(BuildStepError::new_simple(format!("invalid SHA-256 checksum \"{}\"", &self.sha256)).into());
+        }
+
+        let archive_path = self.cached_archive()?;
+        let mut archive = BuildSourceArchive::builder(archive_path)
+            .strip_leading_component(self.strip_leading_component)
+            .checkout_folder(self.checkout_folder.clone())
+            .build();
+        if let Some(key) = self.checkout_key {
+            archive.separate_checkout(key);
+        }
+
+        archive.setup()?;
+        self.archive = Some(archive);
+        Ok(())
+    }
+
+    fn local_directory(&self) -> &PathBuf {
+        self.archive.as_ref().expect("expected a path")
+            .local_directory()
+    }
+
+    fn separate_checkout(&mut self, key: u64) {
+        self.checkout_key = Some(key);
+    }
+
+    fn cleanup(&mut self) {
+        if let Some(mut archive) = self.archive.take() {
+            archive.cleanup();
+        }
+    }
+}
+
+pub struct BuildSourceUrlBuilder {
+    inner: BuildSourceUrl
+}
+
+impl BuildSourceUrlBuilder {
+    fn new(url: String, sha256: String) -> Self {
+        BuildSourceUrlBuilder {
+            inner: BuildSourceUrl {
+                url,
+                sha256: sha256.to_lowercase(),
+                strip_leading_component: false,
+
+                cache_folder: None,
+                checkout_key: None,
+                checkout_folder: None,
+                offline: None,
+                archive: None
+            }
+        }
+    }
+
+    /// Strip the leading directory of the archive (e.g. `libsrtp-2.3.0/`)
+    pub fn strip_leading_component(mut self, enabled: bool) -> Self {
+        self.inner.strip_leading_component = enabled;
+        self
+    }
+
+    /// Set the directory where downloaded archives will be cached
+    pub fn cache_folder(mut self, path: Option<PathBuf>) -> Self {
+        self.inner.cache_folder = path;
+        self
+    }
+
+    pub fn checkout_folder(mut self, path: Option<PathBuf>) -> Self {
+        self.inner.checkout_folder = path;
+        self
+    }
+
+    /// Never download the archive and only use the cache.
+    /// If not set, the `rbuild_offline` environment variable will be used.
+    pub fn offline(mut self, enabled: Option<bool>) -> Self {
+        self.inner.offline = enabled;
+        self
+    }
+
+    pub fn build(self) -> BuildSourceUrl {
+        self.inner
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::source::{BuildSourceUrl, BuildSource};
+    use crate::source::url::sha256_file;
+    use std::process::Command;
+    use std::net::TcpListener;
+    use std::io::{Read, Write};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Serve the given content for every request on a local http server
+    fn serve_content(content: Vec<u8>) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let requests = Arc::new(AtomicUsize::new(0));
+
+        let request_counter = requests.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => break
+                };
+
+                let mut request = Vec::new();
+                let mut buffer = [0u8; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    match stream.read(&mut buffer) {
+                        Ok(0) | Err(_) => break,
+                        Ok(read) => request.extend_from_slice(&buffer[..read])
+                    }
+                }
+
+                request_counter.fetch_add(1, Ordering::SeqCst);
+                let _ = write!(stream, "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", content.len());
+                let _ = stream.write_all(&content);
+            }
+        });
+
+        (format!("http://{}/releases/libtest-1.0.tar.gz?download=1", address), requests)
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_download_archive() {
+        let base_url = std::env::temp_dir().join("__test_download_archive");
+        let _ = std::fs::remove_dir_all(&base_url);
+
+        let content = base_url.join("content").join("libtest-1.0");
+        std::fs::create_dir_all(&content).unwrap();
+        std::fs::write(content.join("meson.build"), "project('test', 'c')").unwrap();
+
+        let archive = base_url.join("libtest-1.0.tar.gz");
+        let status = Command::new("tar").arg("-czf").arg(&archive).arg("-C").arg(base_url.join("content")).arg("libtest-1.0").status().unwrap();
+        assert!(status.success());
+
+        let checksum = sha256_file(&archive).unwrap();
+        let (url, requests) = serve_content(std::fs::read(&archive).unwrap());
+
+        let create_source = |checksum: &str| {
+            BuildSourceUrl::builder(url.clone(), checksum.to_owned())
+                .cache_folder(Some(base_url.join("cache")))
+                .checkout_folder(Some(base_url.join("checkout")))
+                .strip_leading_component(true)
+                .build()
+        };
+
+        let mut source = create_source(&checksum);
+        source.setup().expect("failed to setup download source");
+        assert!(source.local_directory().join("meson.build").is_file());
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        /* the second setup must use the cached archive */
+        let mut source = create_source(&checksum.to_uppercase());
+        source.setup().expect("failed to setup download source");
+        assert!(source.local_directory().join("meson.build").is_file());
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        /* offline builds may only use the cache */
+        let mut source = BuildSourceUrl::builder(url.clone(), checksum.clone())
+            .cache_folder(Some(base_url.join("cache")))
+            .offline(Some(true))
+            .build();
+        source.setup().expect("failed to setup cached download source offline");
+
+        let mut source = BuildSourceUrl::builder(url.clone(), checksum.clone())
+            .cache_folder(Some(base_url.join("empty_cache")))
+            .offline(Some(true))
+            .build();
+        assert!(source.setup().is_err());
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        let mut source = create_source(&"0".repeat(64));
+        assert!(source.setup().is_err());
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+        let mut source = create_source("1234");
+        assert!(source.setup().is_err());
+
+        std::fs::remove_dir_all(&base_url).unwrap();
+    }
+}
@@ -1,5 +1,5 @@
 use std::env;
-use crate::build::{LibraryType, BuildStepError};
+use crate::build::{LibraryType, BuildStepError, CompilerCache};
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::ops::Deref;
@@ -32,6 +32,9 @@ macro_rules! resolve_env_var {
             None
         }
     }};
+    ($key:expr) => {{
+        env::var(format!("rbuild_{}", $key)).ok()
+    }};
 }
 
 pub enum BuildLibraryTypeError {
@@ -57,6 +60,13 @@ pub fn build_library_type(build_name: &str) -> Result<LibraryType, BuildLibraryT
     }
 }
 
+/// Get the compiler cache configured through the environment.
+/// `Some(None)` indicates that the compiler cache has been disabled explicitly.
+pub fn compiler_cache(build_name: &str) -> Option<Option<CompilerCache>> {
+    resolve_env_var!(build_name, "compiler_cache")
+        .map(|value| CompilerCache::parse(&value))
+}
+
 pub fn install_prefix(build_name: &str) -> Option<PathBuf> {
     if let Some(path) = resolve_env_var!(build_name, "install_prefix")  {
         Some(PathBuf::from(path))
@@ -121,19 +131,31 @@ impl Debug for TemporaryPath {
     }
 }
 
-pub fn create_temporary_path(folder_name: &str, base_dir: Option<&PathBuf>) -> std::io::Result<TemporaryPath> {
-    let path = if let Some(base_dir) = base_dir {
+/// Get the location of a temporary path without creating it
+pub(crate) fn temporary_path_location(folder_name: &str, base_dir: Option<&PathBuf>) -> PathBuf {
+    if let Some(base_dir) = base_dir {
         base_dir.join(folder_name)
     } else if let Ok(path) = env::var("OUT_DIR") {
         /* Seems like a cargo build. Use that directory as temp so we don't junk the system temp directory */
         PathBuf::from(path).join(folder_name)
     } else {
         env::temp_dir().join(folder_name)
-    };
+    }
+}
 
+pub fn create_temporary_path(folder_name: &str, base_dir: Option<&PathBuf>) -> std::io::Result<TemporaryPath> {
+    let path = temporary_path_location(folder_name, base_dir);
     std::fs::create_dir_all(&path).map(|_| TemporaryPath{ inner: Arc::new(TemporaryPathInner{ path, released: false })})
 }
 
+/// Test if sources must not access the network (`rbuild_offline`)
+pub fn offline_mode_enabled() -> bool {
+    match resolve_env_var!("offline") {
+        Some(value) => matches!(value.to_lowercase().as_str(), "1" | "true" | "yes" | "on"),
+        None => false
+    }
+}
+
 fn verbose_commands_enabled() -> bool {
     /* TODO: Some kind of env variable */
     true
@@ -161,7 +183,7 @@ pub fn execute_build_command(command: &mut Command, error_detail: &str) -> Resul
     }
 
     if !output.status.success() {
-        return Err(BuildStepError::new(error_detail.to_owned(), stdout, stderr));
+        return Err(BuildStepError::new_exit_code(error_detail.to_owned(), stdout, stderr, output.status.code()));
     }
 
     Ok((stdout, stderr))